@@ -0,0 +1,96 @@
+use crate::fixed_buf::FixedBuf;
+use crate::vga_buffer::{Writer, WRITER};
+use core::fmt::Write;
+use x86_64::instructions::interrupts;
+
+#[cfg(test)]
+use crate::vga_buffer::BUFFER_HEIGHT;
+
+/// The parts of the boot loader's handoff worth showing at boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootSummary {
+    /// Where the boot loader mapped all of physical memory.
+    pub physical_memory_offset: u64,
+    pub memory_regions: usize,
+    /// The total size of the regions marked usable, in bytes.
+    pub usable_bytes: u64,
+    /// Whether the boot loader set up a graphical framebuffer. The `bootloader`
+    /// 0.9 series always leaves the screen in VGA text mode.
+    pub framebuffer: bool,
+}
+
+#[cfg(feature = "boot_info")]
+impl BootSummary {
+    pub fn from_boot_info(boot_info: &bootloader::BootInfo) -> BootSummary {
+        use bootloader::bootinfo::MemoryRegionType;
+
+        const FRAME_SIZE: u64 = 4096;
+
+        let usable_bytes = boot_info
+            .memory_map
+            .iter()
+            .filter(|region| region.region_type == MemoryRegionType::Usable)
+            .map(|region| {
+                (region.range.end_frame_number - region.range.start_frame_number) * FRAME_SIZE
+            })
+            .sum();
+        BootSummary {
+            physical_memory_offset: boot_info.physical_memory_offset,
+            memory_regions: boot_info.memory_map.iter().count(),
+            usable_bytes,
+            framebuffer: false,
+        }
+    }
+}
+
+/// Prints `summary` as a list of settings.
+pub fn print_boot_summary(summary: &BootSummary) {
+    interrupts::without_interrupts(|| write_boot_summary(&mut WRITER.lock(), summary));
+}
+
+fn write_boot_summary(writer: &mut Writer, summary: &BootSummary) {
+    let mut value = FixedBuf::<32>::new();
+    let _ = write!(value, "{:#x}", summary.physical_memory_offset);
+    writer.write_kv("physical memory at", value.as_str());
+
+    let mut value = FixedBuf::<32>::new();
+    let _ = write!(value, "{}", summary.memory_regions);
+    writer.write_kv("memory regions", value.as_str());
+
+    let mut value = FixedBuf::<32>::new();
+    let _ = write!(value, "{} KiB", summary.usable_bytes / 1024);
+    writer.write_kv("usable memory", value.as_str());
+
+    let framebuffer = if summary.framebuffer {
+        "present"
+    } else {
+        "none"
+    };
+    writer.write_kv("framebuffer", framebuffer);
+}
+
+#[test_case]
+fn test_print_boot_summary() {
+    WRITER.lock().clear_screen();
+    print_boot_summary(&BootSummary {
+        physical_memory_offset: 0x100_0000_0000,
+        memory_regions: 7,
+        usable_bytes: 130_048 * 1024,
+        framebuffer: false,
+    });
+
+    let screen = WRITER.lock().snapshot();
+    let expected: [&[u8]; 4] = [
+        b"physical memory at: 0x10000000000",
+        b"memory regions:     7",
+        b"usable memory:      130048 KiB",
+        b"framebuffer:        none",
+    ];
+    for (i, &text) in expected.iter().enumerate() {
+        let row = &screen.0[BUFFER_HEIGHT - 5 + i];
+        for (col, cell) in row.iter().enumerate() {
+            let byte = text.get(col).copied().unwrap_or(b' ');
+            assert_eq!(cell.ascii_character, byte, "line {}, column {}", i, col);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::serial;
+use crate::vga_buffer::WRITER;
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::instructions::interrupts;
+
+#[cfg(test)]
+use crate::vga_buffer::BUFFER_HEIGHT;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+const SQUARE: u8 = 0xfe;
+
+static LAST_WAS_CR: AtomicBool = AtomicBool::new(false);
+
+/// Runs a full-screen program on the alternate screen.
+///
+/// `app` starts on a blank screen and the output from before comes back once
+/// it returns. By convention apps return when `keyboard::pop` gives them
+/// `KeyEvent::Pressed(Key::Escape)`.
+pub fn run_fullscreen<F: FnOnce()>(app: F) {
+    interrupts::without_interrupts(|| WRITER.lock().enter_alternate());
+    app();
+    interrupts::without_interrupts(|| WRITER.lock().leave_alternate());
+}
+
+/// Turns the kernel into a dumb terminal driven from the host over serial.
+///
+/// Every byte received is drawn on the screen. Never returns.
+pub fn serial_terminal() -> ! {
+    loop {
+        poll_serial_terminal();
+    }
+}
+
+/// Draws the next byte received over serial, returning whether there was one.
+///
+/// A carriage return, a line feed or both in sequence start a new line.
+/// Backspace and delete erase the character before the cursor. Other bytes
+/// outside printable ASCII show up as a square, as with `print!`.
+pub fn poll_serial_terminal() -> bool {
+    let byte = match serial::read_byte() {
+        Some(byte) => byte,
+        None => return false,
+    };
+
+    let last_was_cr = LAST_WAS_CR.swap(byte == b'\r', Ordering::Relaxed);
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        match byte {
+            b'\n' if last_was_cr => {}
+            b'\r' | b'\n' => writer.write_byte(b'\n'),
+            BACKSPACE | DELETE => writer.backspace(),
+            0x20..=0x7e | b'\t' => writer.write_byte(byte),
+            _ => writer.write_byte(SQUARE),
+        }
+        writer.update_cursor();
+    });
+
+    true
+}
+
+#[test_case]
+fn test_serial_terminal() {
+    WRITER.lock().clear_screen();
+
+    serial::set_loopback(true);
+    let input = b"hi\r\nx\x08y\rz";
+    for &byte in input.iter() {
+        serial::send_raw(byte);
+    }
+    let mut handled = 0;
+    for _ in 0..100_000 {
+        if poll_serial_terminal() {
+            handled += 1;
+            if handled == input.len() {
+                break;
+            }
+        }
+    }
+    serial::set_loopback(false);
+
+    let writer = WRITER.lock();
+    let char_at = |row: usize, col: usize| writer.read_char(row, col).unwrap().ascii_character;
+    assert_eq!(handled, input.len());
+    assert_eq!(
+        (char_at(BUFFER_HEIGHT - 3, 0), char_at(BUFFER_HEIGHT - 3, 1)),
+        (b'h', b'i')
+    );
+    assert_eq!(
+        (char_at(BUFFER_HEIGHT - 2, 0), char_at(BUFFER_HEIGHT - 2, 1)),
+        (b'y', b' ')
+    );
+    assert_eq!(char_at(BUFFER_HEIGHT - 1, 0), b'z');
+}
+
+#[test_case]
+fn test_run_fullscreen() {
+    use crate::keyboard::{self, Key, KeyEvent};
+
+    WRITER.lock().clear_screen();
+    crate::print!("before");
+    let before = WRITER.lock().snapshot();
+
+    for &scancode in [0x1E, 0x9E, 0x01, 0x81].iter() {
+        keyboard::push_scancode(scancode);
+    }
+    let mut events = 0;
+    let mut inside = None;
+    run_fullscreen(|| loop {
+        match keyboard::pop() {
+            Some(KeyEvent::Pressed(Key::Escape)) => break,
+            Some(_) => {
+                events += 1;
+                crate::print!("key");
+                inside = Some(WRITER.lock().snapshot());
+            }
+            None => {}
+        }
+    });
+    let leftover = keyboard::pop();
+
+    assert_eq!(events, 2);
+    assert_eq!(inside.unwrap().0[0][0].ascii_character, b'k');
+    assert_eq!(WRITER.lock().snapshot(), before);
+    assert_eq!(leftover, Some(KeyEvent::Released(Key::Escape)));
+}
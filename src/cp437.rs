@@ -0,0 +1,77 @@
+/// Unicode equivalents of every Code Page 437 byte, the VGA text mode font.
+///
+/// Bytes below 0x20 and 0x7F are the font's graphical glyphs, not control
+/// characters.
+#[rustfmt::skip]
+static TABLE: [char; 256] = [
+    '\0', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+    ' ', '!', '"', '#', '$', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/',
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?',
+    '@', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '[', '\\', ']', '^', '_',
+    '`', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '{', '|', '}', '~', '⌂',
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Returns the Unicode character the VGA font draws for `byte`.
+pub fn to_unicode(byte: u8) -> char {
+    TABLE[byte as usize]
+}
+
+/// Returns the Code Page 437 byte that draws `c`, if the font has it.
+pub fn from_unicode(c: char) -> Option<u8> {
+    match c {
+        ' '..='~' => Some(c as u8),
+        _ => TABLE
+            .iter()
+            .position(|&glyph| glyph == c)
+            .map(|byte| byte as u8),
+    }
+}
+
+#[test_case]
+fn test_ascii_maps_to_itself() {
+    for byte in 0x20..0x7f {
+        assert_eq!(to_unicode(byte), char::from(byte));
+        assert_eq!(from_unicode(char::from(byte)), Some(byte));
+    }
+}
+
+#[test_case]
+fn test_round_trip_glyphs() {
+    let glyphs = [
+        ('─', 0xc4),
+        ('│', 0xb3),
+        ('┌', 0xda),
+        ('╬', 0xce),
+        ('█', 0xdb),
+        ('░', 0xb0),
+        ('é', 0x82),
+        ('á', 0xa0),
+        ('ç', 0x87),
+        ('Ñ', 0xa5),
+        ('☺', 0x01),
+        ('⌂', 0x7f),
+    ];
+
+    for &(glyph, byte) in glyphs.iter() {
+        assert_eq!(from_unicode(glyph), Some(byte), "failed on {}", glyph);
+        assert_eq!(to_unicode(byte), glyph, "failed on {:#x}", byte);
+    }
+}
+
+#[test_case]
+fn test_unmapped_characters() {
+    assert_eq!(from_unicode('ã'), None);
+    assert_eq!(from_unicode('€'), None);
+    assert_eq!(from_unicode('\n'), None);
+}
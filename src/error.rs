@@ -0,0 +1,129 @@
+use crate::serial::FrameError;
+use core::fmt;
+
+#[cfg(test)]
+use crate::fixed_buf::FixedBuf;
+#[cfg(test)]
+use crate::vga_buffer::capture_into;
+
+/// Why one of the crate's fallible operations failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelError {
+    /// A cell outside the screen.
+    OutOfBounds { row: usize, col: usize },
+    /// A value that is not one of the 16 VGA colors.
+    InvalidColor(u8),
+    /// The UART only sends 5 to 8 data bits.
+    InvalidDataBits(u8),
+    /// The UART only sends 1 or 2 stop bits.
+    InvalidStopBits(u8),
+    /// A serial frame that could not be sent or received.
+    InvalidFrame(FrameError),
+    /// A scroll region that overlaps the reserved rows or leaves the screen.
+    InvalidLayout,
+    /// A string that does not fit on the rest of its row.
+    LineTooLong {
+        /// The cells the string needs, or `None` if it has a newline.
+        width: Option<usize>,
+        /// The cells left on the row.
+        available: usize,
+    },
+}
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KernelError::OutOfBounds { row, col } => {
+                write!(f, "cell at row {}, column {} is off screen", row, col)
+            }
+            KernelError::InvalidColor(value) => write!(f, "invalid color {:#x}", value),
+            KernelError::InvalidDataBits(bits) => {
+                write!(f, "{} data bits not supported, expected 5 to 8", bits)
+            }
+            KernelError::InvalidStopBits(bits) => {
+                write!(f, "{} stop bits not supported, expected 1 or 2", bits)
+            }
+            KernelError::InvalidFrame(FrameError::Timeout) => write!(f, "serial frame timed out"),
+            KernelError::InvalidFrame(FrameError::TooLong(len)) => {
+                write!(f, "serial frame of {} bytes is too long", len)
+            }
+            KernelError::InvalidFrame(FrameError::BadChecksum { expected, actual }) => write!(
+                f,
+                "serial frame checksum {:#06x} does not match {:#06x}",
+                actual, expected
+            ),
+            KernelError::InvalidLayout => write!(f, "scroll region does not fit the layout"),
+            KernelError::LineTooLong {
+                width: Some(width),
+                available,
+            } => write!(f, "line needs {} cells, {} left", width, available),
+            KernelError::LineTooLong {
+                width: None,
+                available: _,
+            } => write!(f, "line contains a newline"),
+        }
+    }
+}
+
+#[cfg(test)]
+fn display(error: KernelError) -> FixedBuf<64> {
+    let mut buf = FixedBuf::new();
+    capture_into(&mut buf, || crate::print!("{}", error));
+    buf
+}
+
+#[test_case]
+fn test_display() {
+    let cases = [
+        (
+            KernelError::OutOfBounds { row: 25, col: 3 },
+            "cell at row 25, column 3 is off screen",
+        ),
+        (KernelError::InvalidColor(0x10), "invalid color 0x10"),
+        (
+            KernelError::InvalidDataBits(9),
+            "9 data bits not supported, expected 5 to 8",
+        ),
+        (
+            KernelError::InvalidStopBits(3),
+            "3 stop bits not supported, expected 1 or 2",
+        ),
+        (
+            KernelError::InvalidFrame(FrameError::Timeout),
+            "serial frame timed out",
+        ),
+        (
+            KernelError::InvalidFrame(FrameError::TooLong(300)),
+            "serial frame of 300 bytes is too long",
+        ),
+        (
+            KernelError::InvalidFrame(FrameError::BadChecksum {
+                expected: 0x29b1,
+                actual: 0x1234,
+            }),
+            "serial frame checksum 0x1234 does not match 0x29b1",
+        ),
+        (
+            KernelError::InvalidLayout,
+            "scroll region does not fit the layout",
+        ),
+        (
+            KernelError::LineTooLong {
+                width: Some(90),
+                available: 80,
+            },
+            "line needs 90 cells, 80 left",
+        ),
+        (
+            KernelError::LineTooLong {
+                width: None,
+                available: 80,
+            },
+            "line contains a newline",
+        ),
+    ];
+
+    for &(error, expected) in cases.iter() {
+        assert_eq!(display(error).as_str(), expected);
+    }
+}
@@ -0,0 +1,128 @@
+use core::fmt;
+
+/// A string of up to `N` bytes kept on the stack, for formatting without an
+/// allocator.
+///
+/// Writing past the capacity drops the rest instead of failing, so a message
+/// cut short still shows what fit. Text is only cut between characters.
+pub struct FixedBuf<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedBuf<N> {
+    pub const fn new() -> FixedBuf<N> {
+        FixedBuf {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    /// The text written so far.
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// The most digits a `u64` takes in decimal.
+pub const DECIMAL_DIGITS: usize = 20;
+
+/// Writes `value` in decimal, without going through `core::fmt`, so panic
+/// handlers can use it.
+pub fn decimal(mut value: u64) -> FixedBuf<DECIMAL_DIGITS> {
+    let mut buf = FixedBuf::new();
+    let mut start = DECIMAL_DIGITS;
+    loop {
+        start -= 1;
+        buf.bytes[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    buf.bytes.copy_within(start.., 0);
+    buf.len = DECIMAL_DIGITS - start;
+    buf
+}
+
+impl<const N: usize> Default for FixedBuf<N> {
+    fn default() -> FixedBuf<N> {
+        FixedBuf::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for FixedBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut end = s.len().min(N - self.len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.bytes[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_format() {
+    use core::fmt::Write;
+
+    let mut buf = FixedBuf::<32>::new();
+    assert!(buf.is_empty());
+    write!(buf, "{} + {} = {}", 1, 2, 1 + 2).unwrap();
+    assert_eq!(buf.as_str(), "1 + 2 = 3");
+    assert_eq!(buf.len(), 9);
+}
+
+#[test_case]
+fn test_exact_fill() {
+    use core::fmt::Write;
+
+    let mut buf = FixedBuf::<4>::new();
+    write!(buf, "ab").unwrap();
+    write!(buf, "cd").unwrap();
+    assert_eq!(buf.as_str(), "abcd");
+    write!(buf, "e").unwrap();
+    assert_eq!(buf.as_str(), "abcd");
+}
+
+#[test_case]
+fn test_decimal() {
+    for &(value, text) in [
+        (0, "0"),
+        (9, "9"),
+        (10, "10"),
+        (u64::from(u32::MAX), "4294967295"),
+        (u64::MAX, "18446744073709551615"),
+    ]
+    .iter()
+    {
+        assert_eq!(decimal(value).as_str(), text);
+    }
+}
+
+#[test_case]
+fn test_truncate() {
+    use core::fmt::Write;
+
+    let mut buf = FixedBuf::<5>::new();
+    write!(buf, "value {}", 12345).unwrap();
+    assert_eq!(buf.as_str(), "value");
+
+    let mut buf = FixedBuf::<4>::new();
+    write!(buf, "ab\u{e9}\u{e9}").unwrap();
+    assert_eq!(buf.as_str(), "ab\u{e9}");
+    assert_eq!(buf.len(), 4);
+
+    let mut buf = FixedBuf::<3>::new();
+    write!(buf, "ab\u{e9}").unwrap();
+    assert_eq!(buf.as_str(), "ab");
+}
@@ -0,0 +1,148 @@
+use crate::{serial, vga_buffer};
+use x86_64::instructions::interrupts;
+
+#[cfg(test)]
+use crate::println;
+#[cfg(test)]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Runs the kernel without a screen, sending all `println!` output to serial.
+///
+/// Meant to be called first thing in `_start`, before anything is printed.
+pub fn set_headless(headless: bool) {
+    vga_buffer::set_enabled(!headless);
+    vga_buffer::set_serial_mirror(headless);
+}
+
+/// Whether this kernel was built with debug assertions, as `build_info`
+/// shows it.
+pub const PROFILE: &str = if cfg!(debug_assertions) {
+    "debug"
+} else {
+    "release"
+};
+
+/// Prints the package name, version and build profile of the kernel.
+pub fn build_info() {
+    crate::println!("package: {}", env!("CARGO_PKG_NAME"));
+    crate::println!("version: {}", env!("CARGO_PKG_VERSION"));
+    crate::println!("profile: {}", PROFILE);
+}
+
+/// The kernel's main loop: calls `idle` over and over, forever.
+///
+/// `idle` is where periodic work goes, such as draining serial input or
+/// redrawing the debug overlay. Pass `x86_64::instructions::hlt` to sleep
+/// until the next interrupt when there is nothing else to do.
+pub fn run(idle: fn()) -> ! {
+    loop {
+        run_for(idle, u64::MAX);
+    }
+}
+
+/// Like `run`, but returns after calling `idle` `iterations` times.
+pub fn run_for(idle: fn(), iterations: u64) {
+    for _ in 0..iterations {
+        idle();
+    }
+}
+
+/// Makes sure everything written so far is visible: waits for serial output
+/// to leave the UART and moves the hardware cursor to the end of the text.
+///
+/// Call it at checkpoints such as right before `exit_qemu`. Panic handlers
+/// call it too, so it never waits for a lock: if the serial port is locked
+/// the flush is skipped, and if the writer is locked the cursor is left where
+/// it is.
+pub fn sync_output() {
+    serial::flush();
+    interrupts::without_interrupts(|| {
+        if let Some(mut writer) = vga_buffer::WRITER.try_lock() {
+            writer.update_cursor();
+        }
+    });
+}
+
+#[test_case]
+fn test_headless_routes_to_serial() {
+    use vga_buffer::{BUFFER_HEIGHT, BUFFER_WIDTH, WRITER};
+
+    let before = WRITER.lock().read_char(BUFFER_HEIGHT - 1, 0).unwrap();
+    let mut screen = [[before; BUFFER_WIDTH]; BUFFER_HEIGHT];
+    for (row, line) in screen.iter_mut().enumerate() {
+        for (col, cell) in line.iter_mut().enumerate() {
+            *cell = WRITER.lock().read_char(row, col).unwrap();
+        }
+    }
+
+    set_headless(true);
+    serial::set_loopback(true);
+    println!("headless");
+    let mut received = [0u8; 9];
+    for byte in received.iter_mut() {
+        *byte = serial::receive_timeout().unwrap_or(0);
+    }
+    serial::set_loopback(false);
+    set_headless(false);
+
+    assert_eq!(&received, b"headless\n");
+    for (row, line) in screen.iter().enumerate() {
+        for (col, cell) in line.iter().enumerate() {
+            assert_eq!(WRITER.lock().read_char(row, col).unwrap(), *cell);
+        }
+    }
+}
+
+#[test_case]
+fn test_sync_output() {
+    use vga_buffer::{linear_index, BUFFER_HEIGHT, WRITER};
+
+    WRITER.lock().clear_screen();
+    for &byte in b"abc".iter() {
+        WRITER.lock().write_byte(byte);
+    }
+    let before = vga_buffer::hardware_cursor();
+
+    sync_output();
+
+    let after = vga_buffer::hardware_cursor();
+    let writer = WRITER.lock();
+    let serial = serial::SERIAL1.lock();
+    sync_output();
+    drop(serial);
+    drop(writer);
+
+    assert_eq!(before, linear_index(BUFFER_HEIGHT - 1, 0).unwrap());
+    assert_eq!(after, linear_index(BUFFER_HEIGHT - 1, 3).unwrap());
+}
+
+#[test_case]
+fn test_build_info() {
+    let mut buf = crate::fixed_buf::FixedBuf::<128>::new();
+    vga_buffer::capture_into(&mut buf, build_info);
+    let text = buf.as_str();
+
+    let profile = if cfg!(debug_assertions) {
+        "profile: debug\n"
+    } else {
+        "profile: release\n"
+    };
+    assert!(text.starts_with("package: alice_os\n"), "{}", text);
+    assert!(text.contains(env!("CARGO_PKG_VERSION")), "{}", text);
+    assert!(text.ends_with(profile), "{}", text);
+}
+
+#[cfg(test)]
+static IDLE_CALLS: AtomicU64 = AtomicU64::new(0);
+
+#[test_case]
+fn test_run_for() {
+    IDLE_CALLS.store(0, Ordering::SeqCst);
+    run_for(
+        || {
+            IDLE_CALLS.fetch_add(1, Ordering::SeqCst);
+        },
+        5,
+    );
+    assert_eq!(IDLE_CALLS.load(Ordering::SeqCst), 5);
+}
@@ -0,0 +1,332 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// How many scancodes the keyboard queue holds before it starts dropping.
+pub const QUEUE_CAPACITY: usize = 64;
+
+/// Which scancode to lose when a full queue receives another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
+}
+
+/// A fixed-size FIFO of scancodes waiting to be decoded.
+pub struct ScancodeQueue {
+    scancodes: [u8; QUEUE_CAPACITY],
+    head: usize,
+    len: usize,
+    policy: OverflowPolicy,
+}
+
+impl ScancodeQueue {
+    pub const fn new(policy: OverflowPolicy) -> ScancodeQueue {
+        ScancodeQueue {
+            scancodes: [0; QUEUE_CAPACITY],
+            head: 0,
+            len: 0,
+            policy,
+        }
+    }
+
+    /// Adds a scancode, returning `false` if the queue was full and one had
+    /// to be dropped.
+    pub fn push(&mut self, scancode: u8) -> bool {
+        if self.len == QUEUE_CAPACITY {
+            if self.policy == OverflowPolicy::DropOldest {
+                self.scancodes[self.head] = scancode;
+                self.head = (self.head + 1) % QUEUE_CAPACITY;
+            }
+            return false;
+        }
+
+        self.scancodes[(self.head + self.len) % QUEUE_CAPACITY] = scancode;
+        self.len += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let scancode = self.scancodes[self.head];
+        self.head = (self.head + 1) % QUEUE_CAPACITY;
+        self.len -= 1;
+        Some(scancode)
+    }
+
+    /// The queued scancodes, oldest first, without removing them.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len).map(move |i| self.scancodes[(self.head + i) % QUEUE_CAPACITY])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+}
+
+const EXTENDED_PREFIX: u8 = 0xE0;
+const RELEASED: u8 = 0x80;
+
+/// A key identified by the decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Escape,
+    Insert,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Delete,
+    /// A key the decoder has no name for, by its scancode set 1 make code.
+    Other(u8),
+    /// An 0xE0-prefixed key the decoder has no name for, by its make code.
+    OtherExtended(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Pressed(Key),
+    Released(Key),
+}
+
+/// Turns scancode set 1 bytes into key events.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decoder {
+    extended: bool,
+}
+
+impl Decoder {
+    pub const fn new() -> Decoder {
+        Decoder { extended: false }
+    }
+
+    /// Feeds one scancode byte, returning an event once a key is complete.
+    pub fn feed(&mut self, scancode: u8) -> Option<KeyEvent> {
+        if scancode == EXTENDED_PREFIX {
+            self.extended = true;
+            return None;
+        }
+
+        let extended = self.extended;
+        self.extended = false;
+
+        let code = scancode & !RELEASED;
+        let key = if extended {
+            match code {
+                0x47 => Key::Home,
+                0x49 => Key::PageUp,
+                0x4F => Key::End,
+                0x51 => Key::PageDown,
+                0x52 => Key::Insert,
+                0x53 => Key::Delete,
+                code => Key::OtherExtended(code),
+            }
+        } else {
+            match code {
+                0x01 => Key::Escape,
+                code => Key::Other(code),
+            }
+        };
+
+        if scancode & RELEASED == 0 {
+            Some(KeyEvent::Pressed(key))
+        } else {
+            Some(KeyEvent::Released(key))
+        }
+    }
+}
+
+static QUEUE: Mutex<ScancodeQueue> = Mutex::new(ScancodeQueue::new(OverflowPolicy::DropOldest));
+static DECODER: Mutex<Decoder> = Mutex::new(Decoder::new());
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Queues a scancode read from the keyboard controller.
+///
+/// This is the entry point for the keyboard interrupt handler. When the queue
+/// is full a scancode is dropped according to the overflow policy and
+/// `dropped_count` goes up.
+pub fn push_scancode(scancode: u8) {
+    if !QUEUE.lock().push(scancode) {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Takes the oldest queued scancode.
+pub fn pop_scancode() -> Option<u8> {
+    interrupts::without_interrupts(|| QUEUE.lock().pop())
+}
+
+/// Decodes queued scancodes into the next key event, if a whole one is queued.
+///
+/// Interrupts are off while the queue is locked, so the keyboard interrupt
+/// handler cannot find it taken.
+pub fn pop() -> Option<KeyEvent> {
+    interrupts::without_interrupts(|| {
+        let mut decoder = DECODER.lock();
+        let mut queue = QUEUE.lock();
+        while let Some(scancode) = queue.pop() {
+            if let Some(event) = decoder.feed(scancode) {
+                return Some(event);
+            }
+        }
+        None
+    })
+}
+
+/// Returns the key event `pop` would return next, leaving it queued.
+pub fn peek() -> Option<KeyEvent> {
+    interrupts::without_interrupts(|| {
+        let mut decoder = *DECODER.lock();
+        let queue = QUEUE.lock();
+        let mut scancodes = queue.iter();
+        scancodes.find_map(|scancode| decoder.feed(scancode))
+    })
+}
+
+/// Chooses which scancode is dropped when the queue overflows.
+pub fn set_overflow_policy(policy: OverflowPolicy) {
+    interrupts::without_interrupts(|| QUEUE.lock().set_policy(policy));
+}
+
+/// How many scancodes were dropped because the queue was full.
+pub fn dropped_count() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+#[test_case]
+fn test_queue_drops_oldest_on_overflow() {
+    let mut queue = ScancodeQueue::new(OverflowPolicy::DropOldest);
+    for scancode in 0..QUEUE_CAPACITY as u8 {
+        assert!(queue.push(scancode));
+    }
+    assert!(!queue.push(0xAA));
+    assert_eq!(queue.len(), QUEUE_CAPACITY);
+
+    assert_eq!(queue.pop(), Some(1));
+    let mut last = None;
+    while let Some(scancode) = queue.pop() {
+        last = Some(scancode);
+    }
+    assert_eq!(last, Some(0xAA));
+    assert!(queue.is_empty());
+}
+
+#[test_case]
+fn test_queue_drops_newest_on_overflow() {
+    let mut queue = ScancodeQueue::new(OverflowPolicy::DropNewest);
+    for scancode in 0..QUEUE_CAPACITY as u8 {
+        queue.push(scancode);
+    }
+    assert!(!queue.push(0xAA));
+    assert_eq!(queue.len(), QUEUE_CAPACITY);
+
+    assert_eq!(queue.pop(), Some(0));
+    let mut last = None;
+    while let Some(scancode) = queue.pop() {
+        last = Some(scancode);
+    }
+    assert_eq!(last, Some(QUEUE_CAPACITY as u8 - 1));
+}
+
+#[test_case]
+fn test_dropped_count() {
+    while pop_scancode().is_some() {}
+    let dropped = dropped_count();
+
+    for _ in 0..QUEUE_CAPACITY + 5 {
+        push_scancode(0x1E);
+    }
+    assert_eq!(dropped_count(), dropped + 5);
+    assert_eq!(QUEUE.lock().len(), QUEUE_CAPACITY);
+
+    while pop_scancode().is_some() {}
+}
+
+#[test_case]
+fn test_decode_navigation_keys() {
+    let keys = [
+        (0x52, Key::Insert),
+        (0x47, Key::Home),
+        (0x4F, Key::End),
+        (0x49, Key::PageUp),
+        (0x51, Key::PageDown),
+        (0x53, Key::Delete),
+    ];
+
+    let mut decoder = Decoder::new();
+    for &(code, key) in keys.iter() {
+        assert_eq!(decoder.feed(EXTENDED_PREFIX), None);
+        assert_eq!(decoder.feed(code), Some(KeyEvent::Pressed(key)));
+        assert_eq!(decoder.feed(EXTENDED_PREFIX), None);
+        assert_eq!(decoder.feed(code | RELEASED), Some(KeyEvent::Released(key)));
+    }
+}
+
+#[test_case]
+fn test_decode_without_prefix() {
+    let mut decoder = Decoder::new();
+    assert_eq!(
+        decoder.feed(0x47),
+        Some(KeyEvent::Pressed(Key::Other(0x47)))
+    );
+    assert_eq!(
+        decoder.feed(0x47 | RELEASED),
+        Some(KeyEvent::Released(Key::Other(0x47)))
+    );
+}
+
+#[test_case]
+fn test_pop_decodes_queued_scancodes() {
+    while pop_scancode().is_some() {}
+
+    push_scancode(EXTENDED_PREFIX);
+    assert_eq!(pop(), None);
+    push_scancode(0x49);
+    push_scancode(EXTENDED_PREFIX);
+    push_scancode(0x49 | RELEASED);
+
+    assert_eq!(pop(), Some(KeyEvent::Pressed(Key::PageUp)));
+    assert_eq!(pop(), Some(KeyEvent::Released(Key::PageUp)));
+    assert_eq!(pop(), None);
+}
+
+#[test_case]
+fn test_decoder_escape() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.feed(0x01), Some(KeyEvent::Pressed(Key::Escape)));
+    assert_eq!(decoder.feed(0x81), Some(KeyEvent::Released(Key::Escape)));
+    assert_eq!(decoder.feed(0xE0), None);
+    assert_eq!(
+        decoder.feed(0x01),
+        Some(KeyEvent::Pressed(Key::OtherExtended(0x01)))
+    );
+}
+
+#[test_case]
+fn test_peek() {
+    while pop_scancode().is_some() {}
+
+    assert_eq!(peek(), None);
+    push_scancode(EXTENDED_PREFIX);
+    push_scancode(0x47);
+    push_scancode(0x01);
+
+    assert_eq!(peek(), Some(KeyEvent::Pressed(Key::Home)));
+    assert_eq!(peek(), Some(KeyEvent::Pressed(Key::Home)));
+    assert_eq!(pop(), Some(KeyEvent::Pressed(Key::Home)));
+    assert_eq!(peek(), Some(KeyEvent::Pressed(Key::Escape)));
+    assert_eq!(pop(), Some(KeyEvent::Pressed(Key::Escape)));
+    assert_eq!(peek(), None);
+    assert!(QUEUE.lock().is_empty());
+}
@@ -4,22 +4,77 @@
 #![test_runner(crate::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
+pub mod boot;
+pub mod console;
+pub mod cp437;
+pub mod error;
+pub mod fixed_buf;
+pub mod kernel;
+pub mod keyboard;
+pub mod log;
+pub mod mem;
+pub mod panic;
+pub mod port;
 pub mod serial;
+pub mod time;
 pub mod vga_buffer;
 
+pub use error::KernelError;
+
 use core::panic::PanicInfo;
 
-pub fn test_runner(tests: &[&dyn Fn()]) {
+pub trait Testable {
+    fn name(&self) -> &'static str;
+
+    /// Runs the test, reporting it over serial, and returns the cycles it took.
+    fn run(&self) -> u64;
+}
+
+impl<T: Fn()> Testable for T {
+    fn name(&self) -> &'static str {
+        core::any::type_name::<T>()
+    }
+
+    fn run(&self) -> u64 {
+        serial_print!("{}...\t", self.name());
+        let cycles = measure_cycles(self);
+        serial_println!("[ok] ({} cycles)", cycles);
+        cycles
+    }
+}
+
+/// Runs `f` and returns how many CPU cycles it took, as counted by the TSC.
+pub fn measure_cycles<F: FnOnce()>(f: F) -> u64 {
+    use core::arch::x86_64::_rdtsc;
+
+    let start = unsafe { _rdtsc() };
+    f();
+    let end = unsafe { _rdtsc() };
+    end.wrapping_sub(start)
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
     serial_println!("Running {} tests", tests.len());
+    let mut slowest: Option<(&str, u64)> = None;
     for test in tests {
-        test();
+        let cycles = test.run();
+        match slowest {
+            Some((_, most)) if most >= cycles => {}
+            _ => slowest = Some((test.name(), cycles)),
+        }
     }
+    if let Some((name, cycles)) = slowest {
+        serial_println!("Slowest test: {} ({} cycles)", name, cycles);
+    }
+    kernel::sync_output();
     exit_qemu(QemuExitCode::Success);
 }
 
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    panic::run_hook(info);
     serial_println!("[failed]\n");
     serial_println!("Error: {}\n", info);
+    kernel::sync_output();
     exit_qemu(QemuExitCode::Failed);
     loop {}
 }
@@ -37,6 +92,17 @@ fn panic(info: &PanicInfo) -> ! {
     test_panic_handler(info);
 }
 
+#[test_case]
+fn test_measure_cycles() {
+    let cycles = measure_cycles(|| {
+        let mut sum = 0u64;
+        for i in 0..10_000 {
+            sum = core::hint::black_box(sum + i);
+        }
+    });
+    assert!(cycles > 0);
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum QemuExitCode {
@@ -45,10 +111,8 @@ pub enum QemuExitCode {
 }
 
 pub fn exit_qemu(exit_code: QemuExitCode) {
-    use x86_64::instructions::port::Port;
+    use port::Port;
 
-    unsafe {
-        let mut port = Port::new(0xf4);
-        port.write(exit_code as u32);
-    }
+    let mut port = unsafe { Port::new(0xf4) };
+    port.write(exit_code as u32);
 }
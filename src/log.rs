@@ -0,0 +1,108 @@
+use crate::time;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+#[cfg(test)]
+use crate::fixed_buf::FixedBuf;
+#[cfg(test)]
+use crate::vga_buffer::capture_into;
+
+/// How important a log message is, most important first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The least important level still logged while logs are suppressed.
+const QUIET_LEVEL: Level = Level::Warn;
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static QUIET_UNTIL: AtomicU64 = AtomicU64::new(0);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Drops messages less important than `level`. The default is `Info`.
+pub fn set_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Drops messages less important than warnings until the tick counter reaches
+/// `tick`, for phases known to be noisy.
+///
+/// The first message logged after that is preceded by "(logs resumed)".
+pub fn suppress_until_tick(tick: u64) {
+    QUIET_UNTIL.store(tick, Ordering::SeqCst);
+    QUIET.store(true, Ordering::SeqCst);
+}
+
+/// Logs a message at the given level, as in `log!(Level::Info, "booted")`.
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => ($crate::log::_log($level, format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) {
+    if level as u8 > MAX_LEVEL.load(Ordering::Relaxed) {
+        return;
+    }
+
+    if QUIET.load(Ordering::SeqCst) {
+        if time::ticks() < QUIET_UNTIL.load(Ordering::SeqCst) {
+            if level > QUIET_LEVEL {
+                return;
+            }
+        } else if QUIET.swap(false, Ordering::SeqCst) {
+            crate::println!("(logs resumed)");
+        }
+    }
+
+    crate::println!("[{}] {}", level, args);
+}
+
+#[test_case]
+fn test_suppress_until_tick() {
+    let mut buf = FixedBuf::<128>::new();
+    capture_into(&mut buf, || {
+        suppress_until_tick(time::ticks() + 2);
+        crate::log!(Level::Info, "dropped");
+        crate::log!(Level::Warn, "kept");
+        time::tick();
+        crate::log!(Level::Debug, "too verbose");
+        crate::log!(Level::Info, "still dropped");
+        time::tick();
+        crate::log!(Level::Info, "back {}", 1);
+        crate::log!(Level::Info, "back {}", 2);
+    });
+
+    let expected = "[WARN] kept\n(logs resumed)\n[INFO] back 1\n[INFO] back 2\n";
+    assert_eq!(buf.as_str(), expected);
+}
+
+#[test_case]
+fn test_set_level() {
+    let mut buf = FixedBuf::<64>::new();
+    capture_into(&mut buf, || {
+        set_level(Level::Error);
+        crate::log!(Level::Warn, "dropped");
+        crate::log!(Level::Error, "failed");
+        set_level(Level::Info);
+    });
+
+    assert_eq!(buf.as_str(), "[ERROR] failed\n");
+}
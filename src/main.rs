@@ -7,25 +7,47 @@
 use alice_os::println;
 use core::panic::PanicInfo;
 
-#[cfg(test)]
-use alice_os::{serial_print, serial_println};
+#[cfg(feature = "boot_info")]
+use bootloader::{entry_point, BootInfo};
+
+#[cfg(feature = "boot_info")]
+entry_point!(kernel_main);
+
+/// The entry point used with the `boot_info` feature, which takes the
+/// `BootInfo` the boot loader passes and prints a summary of it.
+#[cfg(feature = "boot_info")]
+fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    use alice_os::boot::{self, BootSummary};
+
+    alice_os::vga_buffer::init();
+    boot::print_boot_summary(&BootSummary::from_boot_info(boot_info));
+    run()
+}
 
+#[cfg(not(feature = "boot_info"))]
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
+    alice_os::vga_buffer::init();
+    run()
+}
+
+fn run() -> ! {
     println!("Alice OS");
     println!("--------");
-    println!("version: {}", 0.1);
+    alice_os::kernel::build_info();
 
     #[cfg(test)]
     test_main();
 
-    loop {}
+    alice_os::kernel::run(x86_64::instructions::hlt)
 }
 
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
+    alice_os::panic::run_hook(info);
+    alice_os::panic::print(info);
+    alice_os::kernel::sync_output();
     loop {}
 }
 
@@ -37,7 +59,5 @@ fn panic(info: &PanicInfo) -> ! {
 
 #[test_case]
 fn trivial_assertion() {
-    serial_print!("trivial assertion... ");
     assert_eq!(1, 1);
-    serial_println!("[ok]");
 }
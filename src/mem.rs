@@ -0,0 +1,95 @@
+use crate::vga_buffer::{Writer, WRITER};
+use core::fmt::{self, Write};
+use x86_64::instructions::interrupts;
+
+#[cfg(test)]
+use crate::vga_buffer::BUFFER_HEIGHT;
+
+const END_COLUMN: usize = 20;
+const KIND_COLUMN: usize = 40;
+
+/// What a range of physical memory is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Usable,
+    Reserved,
+    Bootloader,
+    Kernel,
+    Other,
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RegionKind::Usable => "usable",
+            RegionKind::Reserved => "reserved",
+            RegionKind::Bootloader => "bootloader",
+            RegionKind::Kernel => "kernel",
+            RegionKind::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A range of physical memory, from `start` up to but not including `end`.
+///
+/// Boot code converts whatever memory map its boot loader passes into these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u64,
+    pub end: u64,
+    pub kind: RegionKind,
+}
+
+/// Prints `regions` as a table with the start, end and type of each one.
+pub fn print_memory_map(regions: &[Region]) {
+    interrupts::without_interrupts(|| {
+        write_memory_map(&mut WRITER.lock(), regions).unwrap();
+    });
+}
+
+fn write_memory_map(writer: &mut Writer, regions: &[Region]) -> fmt::Result {
+    writer.write_then_tab_to("start", END_COLUMN);
+    writer.write_then_tab_to("end", KIND_COLUMN);
+    writer.write_str("type\n")?;
+
+    for region in regions {
+        write!(writer, "{:#018x}", region.start)?;
+        writer.write_then_tab_to("", END_COLUMN);
+        write!(writer, "{:#018x}", region.end)?;
+        writer.write_then_tab_to("", KIND_COLUMN);
+        writeln!(writer, "{}", region.kind)?;
+    }
+    Ok(())
+}
+
+#[test_case]
+fn test_print_memory_map() {
+    WRITER.lock().clear_screen();
+    print_memory_map(&[
+        Region {
+            start: 0,
+            end: 0x9_fc00,
+            kind: RegionKind::Usable,
+        },
+        Region {
+            start: 0x10_0000,
+            end: 0x40_0000,
+            kind: RegionKind::Kernel,
+        },
+    ]);
+
+    let screen = WRITER.lock().snapshot();
+    let expected: [&[u8]; 3] = [
+        b"start               end                 type",
+        b"0x0000000000000000  0x000000000009fc00  usable",
+        b"0x0000000000100000  0x0000000000400000  kernel",
+    ];
+    for (i, &text) in expected.iter().enumerate() {
+        let row = &screen.0[BUFFER_HEIGHT - 4 + i];
+        for (col, cell) in row.iter().enumerate() {
+            let byte = text.get(col).copied().unwrap_or(b' ');
+            assert_eq!(cell.ascii_character, byte, "line {}, column {}", i, col);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use crate::cp437;
+use crate::fixed_buf::{self, FixedBuf};
+use crate::serial;
+use crate::vga_buffer::{self, Color, BUFFER_WIDTH, WRITER};
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+
+static HOOK: Mutex<Option<fn(&PanicInfo)>> = Mutex::new(None);
+static HOOK_RUNNING: AtomicBool = AtomicBool::new(false);
+static PANIC_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Registers a function the panic handler calls before printing the panic.
+///
+/// Replaces any hook registered before. Without a hook the panic handler only
+/// prints the panic message.
+pub fn set_hook(hook: fn(&PanicInfo)) {
+    *HOOK.lock() = Some(hook);
+}
+
+/// Calls the registered hook, if any. Meant for panic handlers.
+///
+/// A panic raised inside the hook reaches the panic handler again; this call
+/// then returns without running the hook a second time.
+pub fn run_hook(info: &PanicInfo) {
+    PANIC_COUNT.fetch_add(1, Ordering::Relaxed);
+    if HOOK_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let hook = HOOK.try_lock().and_then(|hook| *hook);
+    if let Some(hook) = hook {
+        hook(info);
+    }
+}
+
+/// How many panics have reached `run_hook` since boot.
+pub fn count() -> u64 {
+    PANIC_COUNT.load(Ordering::Relaxed)
+}
+
+/// Prints the panic message and location to the screen and serial.
+///
+/// Panics with a plain string message, the common case, are written piece by
+/// piece without going through `core::fmt`, so very little code runs while
+/// the kernel is already failing. Formatted messages fall back to formatting
+/// `info`. The panic may have happened while the screen or the serial port
+/// was locked, so neither lock is waited for: the screen half is skipped and
+/// serial output goes through `serial::panic_write`.
+pub fn print(info: &PanicInfo) {
+    print_to(info, &mut serial::panic_write);
+}
+
+/// Like `print`, but writes what would go to serial to `serial` instead:
+/// the report followed by the recent output.
+pub fn print_to(info: &PanicInfo, serial: &mut dyn FnMut(&str)) {
+    let (location, message) = match (info.location(), info.message().as_str()) {
+        (Some(location), Some(message)) => (location, message),
+        _ => {
+            let _ = writeln!(Pieces(serial), "{}", info);
+            write_recent_output(serial);
+            if let Some(mut writer) = WRITER.try_lock() {
+                let _ = writeln!(writer, "{}", info);
+            }
+            return;
+        }
+    };
+
+    let (file, line, column) = (location.file(), location.line(), location.column());
+    write_report(serial, file, line, column, message);
+    // Before the report reaches the screen, so it is not one of the lines.
+    write_recent_output(serial);
+    if let Some(mut writer) = WRITER.try_lock() {
+        write_report(
+            &mut |piece| writer.write_string(piece),
+            file,
+            line,
+            column,
+            message,
+        );
+    }
+}
+
+/// Formats into a function that takes the text piece by piece.
+struct Pieces<'a>(&'a mut dyn FnMut(&str));
+
+impl Write for Pieces<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        (self.0)(s);
+        Ok(())
+    }
+}
+
+/// How many lines of recent output a panic report includes.
+pub const RECENT_OUTPUT_LINES: usize = 5;
+
+/// Writes the last lines printed to the screen to `out`, under a
+/// "Recent output:" label.
+///
+/// The lines are kept apart from the screen lock, so they are there even
+/// when the panic happened inside the writer. This never waits: if the panic
+/// interrupted the recording of a line, the lines are left out. Each cell is
+/// written as the Unicode character the VGA font draws for it.
+pub fn write_recent_output(out: &mut dyn FnMut(&str)) {
+    out("Recent output:\n");
+    let recent = match vga_buffer::try_recent_lines(RECENT_OUTPUT_LINES) {
+        Some(recent) => recent,
+        None => {
+            out("  (recent output locked)\n");
+            return;
+        }
+    };
+
+    for line in recent.iter() {
+        out("  ");
+        for &byte in line {
+            let glyph = match cp437::to_unicode(byte) {
+                '\0' => '?',
+                glyph => glyph,
+            };
+            out(glyph.encode_utf8(&mut [0; 4]));
+        }
+        out("\n");
+    }
+}
+
+/// Like `assert!`, but when the condition is false it first shows the
+/// condition, file and line in a red banner on the screen and on serial,
+/// then panics.
+#[macro_export]
+macro_rules! kassert {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            $crate::panic::assert_failed(stringify!($cond), file!(), line!());
+        }
+    };
+}
+
+/// Reports a failed `kassert!` and panics. Use the macro instead.
+#[doc(hidden)]
+#[cold]
+#[track_caller]
+pub fn assert_failed(condition: &str, file: &str, line: u32) -> ! {
+    write_assert_message(&mut serial::panic_write, condition, file, line);
+    serial::panic_write("\n");
+
+    let mut text = FixedBuf::<{ 2 * BUFFER_WIDTH }>::new();
+    write_assert_message(
+        &mut |piece| {
+            let _ = text.write_str(piece);
+        },
+        condition,
+        file,
+        line,
+    );
+    // kassert! may fail while the screen is locked; skip the banner then.
+    if let Some(mut writer) = WRITER.try_lock() {
+        writer.write_banner(text.as_str(), Color::White, Color::Red);
+    }
+
+    panic!("assertion failed: {}", condition);
+}
+
+/// Writes `assertion failed: CONDITION at FILE:LINE` to `out`.
+pub fn write_assert_message(out: &mut dyn FnMut(&str), condition: &str, file: &str, line: u32) {
+    out("assertion failed: ");
+    out(condition);
+    out(" at ");
+    out(file);
+    out(":");
+    out(fixed_buf::decimal(u64::from(line)).as_str());
+}
+
+/// Writes `panicked at FILE:LINE:COLUMN: MESSAGE` and a newline to `out`.
+pub fn write_report(out: &mut dyn FnMut(&str), file: &str, line: u32, column: u32, message: &str) {
+    out("panicked at ");
+    out(file);
+    out(":");
+    out(fixed_buf::decimal(u64::from(line)).as_str());
+    out(":");
+    out(fixed_buf::decimal(u64::from(column)).as_str());
+    out(": ");
+    out(message);
+    out("\n");
+}
+
+#[test_case]
+fn test_write_report() {
+    let mut text = FixedBuf::<64>::new();
+    write_report(
+        &mut |piece| {
+            let _ = text.write_str(piece);
+        },
+        "src/main.rs",
+        42,
+        7,
+        "boom",
+    );
+    assert_eq!(text.as_str(), "panicked at src/main.rs:42:7: boom\n");
+}
+
+#[test_case]
+fn test_write_assert_message() {
+    let mut text = FixedBuf::<64>::new();
+    write_assert_message(
+        &mut |piece| {
+            let _ = text.write_str(piece);
+        },
+        "ready",
+        "src/main.rs",
+        42,
+    );
+    assert_eq!(text.as_str(), "assertion failed: ready at src/main.rs:42");
+}
+
+#[test_case]
+fn test_write_recent_output() {
+    crate::println!("café ─ ok");
+    // Room for every line to be full of three byte characters.
+    let mut text = FixedBuf::<{ RECENT_OUTPUT_LINES * (3 * BUFFER_WIDTH + 3) + 15 }>::new();
+    write_recent_output(&mut |piece| {
+        let _ = text.write_str(piece);
+    });
+    assert!(
+        text.as_str().starts_with("Recent output:\n"),
+        "{}",
+        text.as_str()
+    );
+    assert!(
+        text.as_str().ends_with("  café ─ ok\n"),
+        "{}",
+        text.as_str()
+    );
+}
+
+#[test_case]
+fn test_kassert_passes() {
+    crate::kassert!(1 + 1 == 2);
+}
@@ -0,0 +1,93 @@
+//! Typed access to x86 I/O ports.
+//!
+//! Creating a `Port` is the unsafe step: the caller promises the address
+//! belongs to the device it expects and that reading or writing it cannot
+//! break memory safety. Once created, reads and writes are safe.
+
+use core::marker::PhantomData;
+
+/// A value that can be moved through an I/O port in one access.
+pub trait PortValue: Copy {
+    /// # Safety
+    ///
+    /// The same rules as `Port::new` apply to `port`.
+    unsafe fn read_from(port: u16) -> Self;
+
+    /// # Safety
+    ///
+    /// The same rules as `Port::new` apply to `port`.
+    unsafe fn write_to(port: u16, value: Self);
+}
+
+macro_rules! impl_port_value {
+    ($t:ty) => {
+        impl PortValue for $t {
+            unsafe fn read_from(port: u16) -> $t {
+                x86_64::instructions::port::Port::<$t>::new(port).read()
+            }
+
+            unsafe fn write_to(port: u16, value: $t) {
+                x86_64::instructions::port::Port::<$t>::new(port).write(value)
+            }
+        }
+    };
+}
+
+impl_port_value!(u8);
+impl_port_value!(u16);
+impl_port_value!(u32);
+
+/// A read/write I/O port carrying values of type `T`.
+pub struct Port<T> {
+    port: u16,
+    phantom: PhantomData<T>,
+}
+
+impl<T: PortValue> Port<T> {
+    /// Creates a port at the given I/O address.
+    ///
+    /// # Safety
+    ///
+    /// Accessing the port must not have side effects that violate memory
+    /// safety, such as reprogramming a DMA controller.
+    pub unsafe fn new(port: u16) -> Port<T> {
+        Port {
+            port,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn read(&mut self) -> T {
+        unsafe { T::read_from(self.port) }
+    }
+
+    pub fn write(&mut self, value: T) {
+        unsafe { T::write_to(self.port, value) }
+    }
+}
+
+#[cfg(test)]
+const SERIAL_SCRATCH: u16 = 0x3F8 + 7;
+
+#[cfg(test)]
+const VBE_INDEX: u16 = 0x1CE;
+
+#[test_case]
+fn test_port_u8_round_trip() {
+    let mut scratch = unsafe { Port::<u8>::new(SERIAL_SCRATCH) };
+    for &value in [0x00, 0x5A, 0xA5, 0xFF].iter() {
+        scratch.write(value);
+        assert_eq!(scratch.read(), value);
+    }
+}
+
+#[test_case]
+fn test_port_u16_round_trip() {
+    let mut index = unsafe { Port::<u16>::new(VBE_INDEX) };
+    let original = index.read();
+    for &value in [0x0001, 0x0003].iter() {
+        index.write(value);
+        assert_eq!(index.read(), value);
+    }
+    index.write(original);
+}
@@ -1,10 +1,116 @@
+use crate::port::Port;
+use crate::vga_buffer::{BUFFER_WIDTH, WRITER};
+use crate::KernelError;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use uart_16550::SerialPort;
+use x86_64::instructions::interrupts;
+
+#[cfg(test)]
+use crate::vga_buffer::{self, BUFFER_HEIGHT};
+
+const COM1: u16 = 0x3F8;
+
+const DATA: u16 = COM1;
+const INTERRUPT_ENABLE: u16 = COM1 + 1;
+const LINE_CONTROL: u16 = COM1 + 3;
+const MODEM_CONTROL: u16 = COM1 + 4;
+const LINE_STATUS: u16 = COM1 + 5;
+
+const INTERRUPT_ENABLE_DATA_AVAILABLE: u8 = 0x01;
+
+const MODEM_CONTROL_DEFAULT: u8 = 0x0B;
+const MODEM_CONTROL_LOOPBACK: u8 = 0x10;
+
+const LINE_STATUS_DATA_READY: u8 = 0x01;
+const LINE_STATUS_OUTPUT_EMPTY: u8 = 0x20;
+const LINE_STATUS_TRANSMITTER_IDLE: u8 = 0x40;
+
+const LINE_CONTROL_TWO_STOP_BITS: u8 = 0x04;
+const LINE_CONTROL_PARITY_ENABLE: u8 = 0x08;
+const LINE_CONTROL_EVEN_PARITY: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+/// How text and bytes written with `serial_print!` and `write_bytes` are
+/// sent, as set by `set_encoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Every byte is sent as is. This is the default.
+    Raw,
+    /// Every byte is sent as two uppercase ASCII hex digits, so output with
+    /// binary data in it can still be captured as text.
+    Hex,
+}
+
+static HEX_ENCODING: AtomicBool = AtomicBool::new(false);
+
+/// How many received bytes wait in the receive buffer before new ones are
+/// dropped.
+pub const RX_BUFFER_SIZE: usize = 256;
+
+/// Received bytes moved out of the UART by `handle_interrupt`.
+struct RxBuffer {
+    bytes: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RxBuffer {
+    const fn new() -> RxBuffer {
+        RxBuffer {
+            bytes: [0; RX_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == RX_BUFFER_SIZE {
+            return false;
+        }
+        self.bytes[(self.head + self.len) % RX_BUFFER_SIZE] = byte;
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.bytes[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+static RX_BUFFER: Mutex<RxBuffer> = Mutex::new(RxBuffer::new());
+
+/// Probe command: dump the cell at the given row and column.
+pub const PROBE_CELL: u8 = b'C';
+/// Probe command: dump the writer state, as `Writer::encode_state` packs it.
+pub const PROBE_STATE: u8 = b'S';
+/// Probe command: dump the characters of a row from the given column on.
+pub const PROBE_ROW: u8 = b'R';
+/// The most characters a `PROBE_ROW` reply carries.
+pub const PROBE_MAX_DATA: usize = 64;
+/// Set in the first byte of a `PROBE_ROW` reply when the row goes on past the
+/// characters sent.
+pub const PROBE_TRUNCATED: u8 = 0x01;
+/// Reply sent for an unknown probe or an out of range cell.
+pub const PROBE_ERROR: u8 = b'?';
 
 lazy_static! {
     pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
+        let mut serial_port = unsafe { SerialPort::new(COM1) };
         serial_port.init();
         Mutex::new(serial_port)
     };
@@ -13,10 +119,398 @@ lazy_static! {
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
     use core::fmt::Write;
-    SERIAL1
-        .lock()
-        .write_fmt(args)
+
+    interrupts::without_interrupts(|| {
+        if encoding() == Encoding::Hex {
+            HexWriter.write_fmt(args)
+        } else {
+            SERIAL1.lock().write_fmt(args)
+        }
         .expect("Printing to serial failed");
+    });
+}
+
+/// Sends everything written to it as hex digits.
+struct HexWriter;
+
+impl fmt::Write for HexWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            send_hex(byte);
+        }
+        Ok(())
+    }
+}
+
+fn send_hex(byte: u8) {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+    send_raw(DIGITS[usize::from(byte >> 4)]);
+    send_raw(DIGITS[usize::from(byte & 0x0f)]);
+}
+
+/// Chooses how `serial_print!` and `write_bytes` send their output.
+///
+/// Frames, probe replies and `send_raw` always send bytes as is.
+pub fn set_encoding(encoding: Encoding) {
+    HEX_ENCODING.store(encoding == Encoding::Hex, Ordering::SeqCst);
+}
+
+/// The encoding chosen with `set_encoding`.
+pub fn encoding() -> Encoding {
+    if HEX_ENCODING.load(Ordering::SeqCst) {
+        Encoding::Hex
+    } else {
+        Encoding::Raw
+    }
+}
+
+/// Sends `bytes` in the current encoding.
+pub fn write_bytes(bytes: &[u8]) {
+    interrupts::without_interrupts(|| {
+        let hex = encoding() == Encoding::Hex;
+        for &byte in bytes {
+            if hex {
+                send_hex(byte);
+            } else {
+                send_raw(byte);
+            }
+        }
+    });
+}
+
+/// Sends a byte as is, without the backspace handling of `SerialPort::send`.
+pub fn send_raw(byte: u8) {
+    let _port = SERIAL1.lock();
+    transmit(byte);
+}
+
+/// Sends `text` from a panic handler without waiting for `SERIAL1`.
+///
+/// The panic may have happened while the port was locked, for example in an
+/// interrupt that fired during `send_raw`. The bytes then go straight to the
+/// UART instead of waiting for a lock that is never released.
+pub fn panic_write(text: &str) {
+    interrupts::without_interrupts(|| match SERIAL1.try_lock() {
+        Some(mut serial) => {
+            for byte in text.bytes() {
+                serial.send(byte);
+            }
+        }
+        None => {
+            for byte in text.bytes() {
+                transmit(byte);
+            }
+        }
+    });
+}
+
+/// Writes a byte to the UART once it can take one. The caller makes sure no
+/// one else is using the port.
+fn transmit(byte: u8) {
+    let mut line_status = unsafe { Port::<u8>::new(LINE_STATUS) };
+    while line_status.read() & LINE_STATUS_OUTPUT_EMPTY == 0 {}
+    unsafe { Port::new(DATA) }.write(byte);
+}
+
+/// Waits until every byte written so far has left the UART.
+///
+/// Panic handlers call this, so it never waits for `SERIAL1`: if the port is
+/// locked the flush is skipped.
+pub fn flush() {
+    if let Some(_port) = SERIAL1.try_lock() {
+        let mut line_status = unsafe { Port::<u8>::new(LINE_STATUS) };
+        while line_status.read() & LINE_STATUS_TRANSMITTER_IDLE == 0 {}
+    }
+}
+
+/// Reads a byte straight from the UART, or `None` if nothing is waiting.
+///
+/// Only the receive buffer may call this; everything else reads through
+/// `read_byte` so no byte is taken out of order.
+fn uart_receive() -> Option<u8> {
+    let _port = SERIAL1.lock();
+    let mut line_status = unsafe { Port::<u8>::new(LINE_STATUS) };
+    if line_status.read() & LINE_STATUS_DATA_READY == 0 {
+        None
+    } else {
+        Some(unsafe { Port::new(DATA) }.read())
+    }
+}
+
+fn receive() -> u8 {
+    loop {
+        if let Some(byte) = read_byte() {
+            return byte;
+        }
+    }
+}
+
+/// Moves every byte waiting in the UART into the receive buffer, returning
+/// how many were moved. Bytes that do not fit are dropped.
+///
+/// This is the body of the COM1 interrupt handler, which fires once
+/// `enable_receive_interrupt` has been called.
+pub fn handle_interrupt() -> usize {
+    drain_uart(&mut RX_BUFFER.lock())
+}
+
+fn drain_uart(buffer: &mut RxBuffer) -> usize {
+    let mut moved = 0;
+    while let Some(byte) = uart_receive() {
+        if buffer.push(byte) {
+            moved += 1;
+        }
+    }
+    moved
+}
+
+/// Makes the UART raise an interrupt whenever a byte arrives (IER bit 0).
+///
+/// `SerialPort::init` already sets this bit, so it is on from boot; calling
+/// this again does no harm.
+pub fn enable_receive_interrupt() {
+    let _port = SERIAL1.lock();
+    let mut interrupt_enable = unsafe { Port::<u8>::new(INTERRUPT_ENABLE) };
+    let value = interrupt_enable.read();
+    interrupt_enable.write(value | INTERRUPT_ENABLE_DATA_AVAILABLE);
+}
+
+/// Returns the oldest received byte, or `None` if nothing is waiting.
+///
+/// This is the only way to read serial input. Bytes still in the UART are
+/// moved to the receive buffer first, so it works the same whether or not the
+/// interrupt handler is draining the UART.
+pub fn read_byte() -> Option<u8> {
+    interrupts::without_interrupts(|| {
+        let mut buffer = RX_BUFFER.lock();
+        drain_uart(&mut buffer);
+        buffer.pop()
+    })
+}
+
+/// Waits a little for a byte, returning `None` if none arrives.
+pub(crate) fn receive_timeout() -> Option<u8> {
+    for _ in 0..100_000 {
+        if let Some(byte) = read_byte() {
+            return Some(byte);
+        }
+    }
+    None
+}
+
+/// Routes transmitted bytes back to the receiver instead of the host.
+pub fn set_loopback(enabled: bool) {
+    let _port = SERIAL1.lock();
+    let value = if enabled {
+        MODEM_CONTROL_DEFAULT | MODEM_CONTROL_LOOPBACK
+    } else {
+        MODEM_CONTROL_DEFAULT
+    };
+    unsafe { Port::new(MODEM_CONTROL) }.write(value);
+}
+
+fn line_control_value(data_bits: u8, parity: Parity, stop_bits: u8) -> Result<u8, KernelError> {
+    let mut value = match data_bits {
+        5..=8 => data_bits - 5,
+        _ => return Err(KernelError::InvalidDataBits(data_bits)),
+    };
+    match stop_bits {
+        1 => {}
+        2 => value |= LINE_CONTROL_TWO_STOP_BITS,
+        _ => return Err(KernelError::InvalidStopBits(stop_bits)),
+    }
+    match parity {
+        Parity::None => {}
+        Parity::Odd => value |= LINE_CONTROL_PARITY_ENABLE,
+        Parity::Even => value |= LINE_CONTROL_PARITY_ENABLE | LINE_CONTROL_EVEN_PARITY,
+    }
+    Ok(value)
+}
+
+/// Sets the number of data bits, the parity and the number of stop bits.
+///
+/// The port starts as 8N1. With 5 data bits, 2 stop bits means 1.5 stop bits,
+/// as the UART defines it. Invalid formats leave the port unchanged.
+pub fn set_line_format(data_bits: u8, parity: Parity, stop_bits: u8) -> Result<(), KernelError> {
+    let value = line_control_value(data_bits, parity, stop_bits)?;
+    let _port = SERIAL1.lock();
+    unsafe { Port::new(LINE_CONTROL) }.write(value);
+    Ok(())
+}
+
+/// Computes the CRC-16/CCITT-FALSE of `bytes`: polynomial 0x1021, starting
+/// from 0xFFFF.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    crc16_update(0xFFFF, bytes)
+}
+
+/// Continues a CRC computed over earlier bytes with `bytes`.
+fn crc16_update(mut crc: u16, bytes: &[u8]) -> u16 {
+    for &byte in bytes {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The largest payload a frame can carry.
+pub const MAX_FRAME_PAYLOAD: usize = 255;
+
+/// Why a frame could not be received or sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame stopped arriving part way through.
+    Timeout,
+    /// The payload is longer than `MAX_FRAME_PAYLOAD` or the receive buffer.
+    TooLong(usize),
+    /// The CRC sent with the frame does not match its contents.
+    BadChecksum { expected: u16, actual: u16 },
+}
+
+/// How many bytes a frame adds around its payload.
+pub const FRAME_OVERHEAD: usize = 3;
+
+/// Writes `payload` as a frame to the start of `out`, returning the frame
+/// length: a length byte, the payload, and the CRC-16 of both, high byte
+/// first.
+///
+/// Fails if the payload is longer than `MAX_FRAME_PAYLOAD` or the frame does
+/// not fit in `out`.
+pub fn encode_frame(payload: &[u8], out: &mut [u8]) -> Result<usize, KernelError> {
+    let frame_len = payload.len() + FRAME_OVERHEAD;
+    if payload.len() > MAX_FRAME_PAYLOAD || frame_len > out.len() {
+        return Err(KernelError::InvalidFrame(FrameError::TooLong(
+            payload.len(),
+        )));
+    }
+
+    let len = payload.len() as u8;
+    let crc = crc16_update(crc16(&[len]), payload);
+    out[0] = len;
+    out[1..frame_len - 2].copy_from_slice(payload);
+    out[frame_len - 2..frame_len].copy_from_slice(&crc.to_be_bytes());
+    Ok(frame_len)
+}
+
+/// Sends `payload` as a frame, as `encode_frame` lays it out.
+pub fn send_frame(payload: &[u8]) -> Result<(), KernelError> {
+    let mut frame = [0u8; MAX_FRAME_PAYLOAD + FRAME_OVERHEAD];
+    let len = encode_frame(payload, &mut frame)?;
+    for &byte in &frame[..len] {
+        send_raw(byte);
+    }
+    Ok(())
+}
+
+/// Receives a frame sent by `send_frame` into `buf`, returning the payload
+/// length.
+///
+/// A frame too long for `buf` is read to its end and dropped, so the next
+/// frame can still be received.
+pub fn recv_frame(buf: &mut [u8]) -> Result<usize, KernelError> {
+    let next = || receive_timeout().ok_or(KernelError::InvalidFrame(FrameError::Timeout));
+
+    let len = next()?;
+    let len_usize = usize::from(len);
+    let fits = len_usize <= buf.len();
+    let mut crc = crc16(&[len]);
+    for i in 0..len_usize {
+        let byte = next()?;
+        crc = crc16_update(crc, &[byte]);
+        if let Some(slot) = buf.get_mut(i) {
+            *slot = byte;
+        }
+    }
+    let expected = u16::from_be_bytes([next()?, next()?]);
+
+    if !fits {
+        return Err(KernelError::InvalidFrame(FrameError::TooLong(len_usize)));
+    }
+    if crc != expected {
+        return Err(KernelError::InvalidFrame(FrameError::BadChecksum {
+            expected,
+            actual: crc,
+        }));
+    }
+    Ok(len_usize)
+}
+
+/// Services one pending probe from the host, returning whether there was one.
+///
+/// Once a command byte arrives this waits for the rest of the probe.
+///
+/// A probe is a command byte followed by its arguments:
+///
+/// - `C row col`: replies with the character and attribute byte of that cell.
+/// - `S`: replies with the `STATE_LEN` bytes of `Writer::encode_state`.
+/// - `R row col`: replies with a frame holding a flags byte and then the
+///   characters of the row from `col` on. At most `PROBE_MAX_DATA` characters
+///   are sent; if the row goes on past them the flags have `PROBE_TRUNCATED`
+///   set and the host can ask again from where the reply stopped.
+///
+/// Unknown commands and out of range cells are answered with `PROBE_ERROR`.
+pub fn handle_probe() -> bool {
+    let command = match read_byte() {
+        Some(command) => command,
+        None => return false,
+    };
+
+    match command {
+        PROBE_CELL => {
+            let row = receive() as usize;
+            let col = receive() as usize;
+            match WRITER.lock().read_char(row, col) {
+                Some(cell) => {
+                    send_raw(cell.ascii_character);
+                    send_raw(cell.color_code.attribute());
+                }
+                None => send_raw(PROBE_ERROR),
+            }
+        }
+        PROBE_STATE => {
+            let state = WRITER.lock().encode_state();
+            for &byte in state.iter() {
+                send_raw(byte);
+            }
+        }
+        PROBE_ROW => {
+            let row = receive() as usize;
+            let col = receive() as usize;
+            let mut payload = [0u8; PROBE_MAX_DATA + 1];
+            match row_dump(row, col, &mut payload) {
+                Some(len) => send_frame(&payload[..len]).expect("row dump fits in a frame"),
+                None => send_raw(PROBE_ERROR),
+            }
+        }
+        _ => send_raw(PROBE_ERROR),
+    }
+
+    true
+}
+
+/// Writes the payload of a `PROBE_ROW` reply for `(row, col)` to `payload`,
+/// returning its length, or `None` if the cell is off screen.
+fn row_dump(row: usize, col: usize, payload: &mut [u8; PROBE_MAX_DATA + 1]) -> Option<usize> {
+    let writer = WRITER.lock();
+    writer.read_char(row, col)?;
+
+    let end = (col + PROBE_MAX_DATA).min(BUFFER_WIDTH);
+    payload[0] = if end < BUFFER_WIDTH {
+        PROBE_TRUNCATED
+    } else {
+        0
+    };
+    for (slot, col) in payload[1..].iter_mut().zip(col..end) {
+        *slot = writer.read_char(row, col)?.ascii_character;
+    }
+    Some(1 + end - col)
 }
 
 /// Prints to the host through the serial interface.
@@ -35,3 +529,279 @@ macro_rules! serial_println {
     ($fmt:expr, $($arg:tt)*) => ($crate::serial_print!(
         concat!($fmt, "\n"), $($arg)*));
 }
+
+#[test_case]
+fn test_probe_cell() {
+    crate::println!();
+    crate::print!("X");
+    let row = BUFFER_HEIGHT - 1;
+    let expected = WRITER.lock().read_char(row, 0).unwrap();
+
+    set_loopback(true);
+    for &byte in [PROBE_CELL, row as u8, 0].iter() {
+        send_raw(byte);
+    }
+    let handled = handle_probe();
+    let reply = (receive_timeout(), receive_timeout());
+    set_loopback(false);
+
+    assert!(handled);
+    assert_eq!(expected.ascii_character, b'X');
+    assert_eq!(reply, (Some(b'X'), Some(expected.color_code.attribute())));
+}
+
+#[test_case]
+fn test_hex_encoding() {
+    assert_eq!(encoding(), Encoding::Raw);
+
+    set_loopback(true);
+    set_encoding(Encoding::Hex);
+    write_bytes(&[0xAB]);
+    crate::serial_print!("\n");
+    set_encoding(Encoding::Raw);
+    write_bytes(&[0xAB]);
+    let mut received = [0u8; 5];
+    for byte in received.iter_mut() {
+        *byte = receive_timeout().unwrap_or(0);
+    }
+    set_loopback(false);
+
+    assert_eq!(&received, b"AB0A\xAB");
+}
+
+#[test_case]
+fn test_set_line_format() {
+    let mut line_control = unsafe { Port::<u8>::new(LINE_CONTROL) };
+
+    assert_eq!(set_line_format(7, Parity::Even, 1), Ok(()));
+    let seven_even_one = line_control.read();
+    assert_eq!(set_line_format(8, Parity::None, 1), Ok(()));
+
+    assert_eq!(seven_even_one, 0x1A);
+    assert_eq!(line_control.read(), 0x03);
+}
+
+#[test_case]
+fn test_invalid_line_format() {
+    assert_eq!(
+        set_line_format(9, Parity::None, 1),
+        Err(KernelError::InvalidDataBits(9))
+    );
+    assert_eq!(
+        set_line_format(8, Parity::Odd, 3),
+        Err(KernelError::InvalidStopBits(3))
+    );
+    assert_eq!(line_control_value(5, Parity::Odd, 2), Ok(0x0C));
+    assert_eq!(line_control_value(6, Parity::Even, 1), Ok(0x19));
+}
+
+#[test_case]
+fn test_flush() {
+    set_loopback(true);
+    for &byte in b"flush".iter() {
+        send_raw(byte);
+    }
+    flush();
+    let idle = unsafe { Port::<u8>::new(LINE_STATUS) }.read() & LINE_STATUS_TRANSMITTER_IDLE;
+    let mut received = [0u8; 5];
+    for byte in received.iter_mut() {
+        *byte = receive_timeout().unwrap_or(0);
+    }
+    set_loopback(false);
+
+    assert_ne!(idle, 0);
+    assert_eq!(&received, b"flush");
+}
+
+#[test_case]
+fn test_panic_write_while_locked() {
+    set_loopback(true);
+    let port = SERIAL1.lock();
+    panic_write("held");
+    drop(port);
+    panic_write("free");
+    let mut received = [0u8; 8];
+    for byte in received.iter_mut() {
+        *byte = receive_timeout().unwrap_or(0);
+    }
+    set_loopback(false);
+
+    assert_eq!(&received, b"heldfree");
+}
+
+#[test_case]
+fn test_receive_buffer() {
+    enable_receive_interrupt();
+    let enabled = unsafe { Port::<u8>::new(INTERRUPT_ENABLE) }.read();
+
+    set_loopback(true);
+    for &byte in b"rx".iter() {
+        send_raw(byte);
+    }
+    flush();
+    let moved = handle_interrupt();
+    set_loopback(false);
+
+    assert_ne!(enabled & INTERRUPT_ENABLE_DATA_AVAILABLE, 0);
+    assert_eq!(moved, 2);
+    assert_eq!(read_byte(), Some(b'r'));
+    assert_eq!(read_byte(), Some(b'x'));
+    assert_eq!(read_byte(), None);
+}
+
+#[test_case]
+fn test_read_byte_keeps_order() {
+    set_loopback(true);
+    for &byte in b"ab".iter() {
+        send_raw(byte);
+    }
+    flush();
+    handle_interrupt();
+    send_raw(b'c');
+    flush();
+    let received = [read_byte(), read_byte(), read_byte(), read_byte()];
+    set_loopback(false);
+
+    assert_eq!(received, [Some(b'a'), Some(b'b'), Some(b'c'), None]);
+}
+
+#[test_case]
+fn test_receive_buffer_drops_when_full() {
+    let mut buffer = RxBuffer::new();
+    for i in 0..RX_BUFFER_SIZE {
+        assert!(buffer.push(i as u8));
+    }
+    assert!(!buffer.push(0xff));
+    assert_eq!(buffer.pop(), Some(0));
+    assert!(buffer.push(0xff));
+    for i in 1..RX_BUFFER_SIZE {
+        assert_eq!(buffer.pop(), Some(i as u8));
+    }
+    assert_eq!(buffer.pop(), Some(0xff));
+    assert_eq!(buffer.pop(), None);
+}
+
+#[test_case]
+fn test_crc16() {
+    assert_eq!(crc16(b"123456789"), 0x29B1);
+    assert_eq!(crc16(b""), 0xFFFF);
+    assert_eq!(crc16(b"A"), 0xB915);
+    assert_eq!(crc16_update(crc16(b"1234"), b"56789"), 0x29B1);
+}
+
+#[test_case]
+fn test_frame_round_trip() {
+    let mut buf = [0u8; 8];
+    let mut small = [0u8; 2];
+
+    set_loopback(true);
+    let sent = send_frame(b"hello");
+    let received = recv_frame(&mut buf);
+    send_frame(b"abc").unwrap();
+    let too_long = recv_frame(&mut small);
+    for &byte in [1, b'x', 0, 0].iter() {
+        send_raw(byte);
+    }
+    let corrupted = recv_frame(&mut buf);
+    let timeout = recv_frame(&mut buf);
+    set_loopback(false);
+
+    assert_eq!(sent, Ok(()));
+    assert_eq!(received, Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+    assert_eq!(
+        too_long,
+        Err(KernelError::InvalidFrame(FrameError::TooLong(3)))
+    );
+    assert_eq!(
+        corrupted,
+        Err(KernelError::InvalidFrame(FrameError::BadChecksum {
+            expected: 0,
+            actual: crc16(&[1, b'x']),
+        }))
+    );
+    assert_eq!(timeout, Err(KernelError::InvalidFrame(FrameError::Timeout)));
+    assert_eq!(
+        send_frame(&[0; MAX_FRAME_PAYLOAD + 1]),
+        Err(KernelError::InvalidFrame(FrameError::TooLong(
+            MAX_FRAME_PAYLOAD + 1
+        )))
+    );
+}
+
+#[test_case]
+fn test_probe_row() {
+    let row = BUFFER_HEIGHT - 1;
+    let mut text = [0u8; BUFFER_WIDTH];
+    for (col, byte) in text.iter_mut().enumerate() {
+        *byte = b'a' + (col % 26) as u8;
+    }
+    {
+        let mut writer = WRITER.lock();
+        writer.clear_screen();
+        writer.write_string(core::str::from_utf8(&text).unwrap());
+    }
+
+    let mut payload = [0u8; PROBE_MAX_DATA + 1];
+    let len = row_dump(row, 0, &mut payload).unwrap();
+    let mut frame = [0u8; PROBE_MAX_DATA + 1 + FRAME_OVERHEAD];
+    let frame_len = encode_frame(&payload[..len], &mut frame).unwrap();
+
+    assert_eq!(frame_len, PROBE_MAX_DATA + 1 + FRAME_OVERHEAD);
+    assert_eq!(usize::from(frame[0]), PROBE_MAX_DATA + 1);
+    assert_eq!(frame[1], PROBE_TRUNCATED);
+    assert_eq!(&frame[2..2 + PROBE_MAX_DATA], &text[..PROBE_MAX_DATA]);
+    let crc = crc16(&frame[..frame_len - 2]);
+    assert_eq!(&frame[frame_len - 2..frame_len], &crc.to_be_bytes());
+
+    // The loopback FIFO only holds 16 bytes, so read back a short tail.
+    let mut reply = [0u8; PROBE_MAX_DATA + 1];
+    set_loopback(true);
+    for &byte in [PROBE_ROW, row as u8, 70].iter() {
+        send_raw(byte);
+    }
+    let handled = handle_probe();
+    let received = recv_frame(&mut reply);
+    for &byte in [PROBE_ROW, row as u8, BUFFER_WIDTH as u8].iter() {
+        send_raw(byte);
+    }
+    handle_probe();
+    let off_screen = receive_timeout();
+    set_loopback(false);
+
+    assert!(handled);
+    assert_eq!(received, Ok(11));
+    assert_eq!(reply[0], 0);
+    assert_eq!(&reply[1..11], &text[70..]);
+    assert_eq!(off_screen, Some(PROBE_ERROR));
+}
+
+#[test_case]
+fn test_probe_state() {
+    {
+        let mut writer = WRITER.lock();
+        writer.clear_screen();
+        writer.home();
+        writer.write_string("abc");
+        writer.set_software_cursor(true);
+    }
+
+    set_loopback(true);
+    send_raw(PROBE_STATE);
+    let handled = handle_probe();
+    let mut reply = [0u8; vga_buffer::STATE_LEN];
+    for byte in reply.iter_mut() {
+        *byte = receive_timeout().unwrap_or(0xff);
+    }
+    set_loopback(false);
+    WRITER.lock().set_software_cursor(false);
+
+    let [row, col, attribute, flags] = reply;
+    assert!(handled);
+    assert_eq!((row, col), (0, 3));
+    assert_eq!(attribute, 0x03);
+    assert_eq!(
+        flags,
+        vga_buffer::STATE_SOFTWARE_CURSOR | vga_buffer::STATE_VGA_ENABLED
+    );
+}
@@ -0,0 +1,96 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// How many times a second the timer interrupt is expected to call `tick`.
+pub const TICKS_PER_SECOND: u64 = 100;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Advances the tick counter by one, returning the new count, and redraws
+/// the debug overlay if it is shown.
+///
+/// The timer interrupt handler calls this on every timer interrupt.
+pub fn tick() -> u64 {
+    let ticks = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    crate::vga_buffer::refresh_debug_overlay();
+    ticks
+}
+
+/// How many timer ticks have happened since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Paces a loop to a fixed number of frames per second, for animations.
+pub struct FrameLimiter {
+    fps: u64,
+    start: u64,
+    frame: u64,
+    idle: fn(),
+}
+
+impl FrameLimiter {
+    /// Creates a limiter for `fps` frames per second. The first frame starts
+    /// now.
+    pub fn new(fps: u32) -> FrameLimiter {
+        FrameLimiter::with_idle(fps, x86_64::instructions::hlt)
+    }
+
+    /// Like `new`, but calls `idle` instead of halting while it waits.
+    pub fn with_idle(fps: u32, idle: fn()) -> FrameLimiter {
+        FrameLimiter {
+            fps: u64::from(fps.max(1)),
+            start: ticks(),
+            frame: 0,
+            idle,
+        }
+    }
+
+    /// Waits until the next frame starts.
+    ///
+    /// A loop that falls behind does not try to catch up: the next frames are
+    /// counted from the moment it noticed.
+    pub fn wait(&mut self) {
+        self.frame += 1;
+        let target = self.start + self.frame * TICKS_PER_SECOND / self.fps;
+        if ticks() > target {
+            self.start = ticks();
+            self.frame = 0;
+            return;
+        }
+
+        while ticks() < target {
+            (self.idle)();
+        }
+    }
+}
+
+#[test_case]
+fn test_tick() {
+    let before = ticks();
+    assert_eq!(tick(), before + 1);
+    tick();
+    assert_eq!(ticks(), before + 2);
+}
+
+#[test_case]
+fn test_frame_limiter() {
+    let mut limiter = FrameLimiter::with_idle(10, || {
+        tick();
+    });
+    let start = ticks();
+    limiter.wait();
+    let first = ticks();
+    limiter.wait();
+    let second = ticks();
+
+    assert_eq!(first - start, TICKS_PER_SECOND / 10);
+    assert_eq!(second - first, TICKS_PER_SECOND / 10);
+
+    for _ in 0..25 {
+        tick();
+    }
+    limiter.wait();
+    assert_eq!(ticks(), second + 25);
+    limiter.wait();
+    assert_eq!(ticks(), second + 25 + TICKS_PER_SECOND / 10);
+}
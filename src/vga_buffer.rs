@@ -1,4 +1,10 @@
+use crate::cp437;
+use crate::fixed_buf::{self, FixedBuf};
+use crate::port::Port;
+use crate::time::FrameLimiter;
+use crate::KernelError;
 use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use volatile::Volatile;
@@ -9,7 +15,21 @@ use crate::{serial_print, serial_println};
 lazy_static! {
     pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
         column_position: 0,
+        row_position: BUFFER_HEIGHT - 1,
         color_code: ColorCode::new(Color::Cyan, Color::Black),
+        clear_color: None,
+        wrap_column: BUFFER_WIDTH,
+        overflow: Overflow::Scroll,
+        discarding: false,
+        frozen: false,
+        wrapped: false,
+        top_row: 0,
+        scroll_margin: 0,
+        software_cursor: false,
+        cursor_cell: None,
+        theme: Some(Theme::DEFAULT),
+        dirty: [None; BUFFER_HEIGHT],
+        alternate: None,
         buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
     });
 }
@@ -36,250 +56,3170 @@ pub enum Color {
     White = 0xf,
 }
 
+impl Color {
+    /// Every color, in attribute byte order.
+    pub const ALL: [Color; 16] = [
+        Color::Black,
+        Color::Blue,
+        Color::Green,
+        Color::Cyan,
+        Color::Red,
+        Color::Magenta,
+        Color::Brown,
+        Color::LightGrey,
+        Color::DarkGray,
+        Color::LightBlue,
+        Color::LightGreen,
+        Color::LightCyan,
+        Color::LightRed,
+        Color::Pink,
+        Color::Yellow,
+        Color::White,
+    ];
+
+    /// A lowercase label for menus and demos, such as "light blue".
+    pub fn name(self) -> &'static str {
+        match self {
+            Color::Black => "black",
+            Color::Blue => "blue",
+            Color::Green => "green",
+            Color::Cyan => "cyan",
+            Color::Red => "red",
+            Color::Magenta => "magenta",
+            Color::Brown => "brown",
+            Color::LightGrey => "light grey",
+            Color::DarkGray => "dark gray",
+            Color::LightBlue => "light blue",
+            Color::LightGreen => "light green",
+            Color::LightCyan => "light cyan",
+            Color::LightRed => "light red",
+            Color::Pink => "pink",
+            Color::Yellow => "yellow",
+            Color::White => "white",
+        }
+    }
+
+    /// Returns the color with attribute value `value`.
+    pub fn from_u8(value: u8) -> Result<Color, KernelError> {
+        match Color::ALL.get(value as usize) {
+            Some(&color) => Ok(color),
+            None => Err(KernelError::InvalidColor(value)),
+        }
+    }
+
+    fn from_nibble(value: u8) -> Color {
+        Color::ALL[(value & 0xf) as usize]
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-struct ColorCode(u8);
+pub struct ColorCode(u8);
 
 impl ColorCode {
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    pub fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
+
+    /// The raw VGA attribute byte.
+    pub fn attribute(self) -> u8 {
+        self.0
+    }
+
+    pub fn foreground(self) -> Color {
+        Color::from_nibble(self.0)
+    }
+
+    pub fn background(self) -> Color {
+        Color::from_nibble(self.0 >> 4)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
-struct ScreenChar {
-    ascii_character: u8,
-    color_code: ColorCode,
+pub struct ScreenChar {
+    pub ascii_character: u8,
+    pub color_code: ColorCode,
+}
+
+impl ScreenChar {
+    pub fn new(ascii_character: u8, foreground: Color, background: Color) -> ScreenChar {
+        ScreenChar {
+            ascii_character,
+            color_code: ColorCode::new(foreground, background),
+        }
+    }
 }
 
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+/// A character with its own colors, as drawn by the cell based methods.
+pub type Cell = ScreenChar;
+
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
+const TAB_WIDTH: usize = 8;
+/// The CP437 box drawing line used by `Writer::hr`.
+const HORIZONTAL_LINE: u8 = 0xC4;
+/// The column `Writer::write_kv` starts values at.
+const KV_VALUE_COLUMN: usize = 20;
+/// The attribute bit that makes a cell blink.
+const BLINK: u8 = 0x80;
+/// How many times a second `Writer::flash_message` turns blinking on or off.
+const FLASH_PHASES_PER_SECOND: u32 = 4;
+/// The glyphs `Writer::sparkline` draws for the values 0 to 8.
+const SPARKLINE_GLYPHS: [u8; 9] = [0x20, 0xB0, 0xB0, 0xB1, 0xB1, 0xB1, 0xB2, 0xB2, 0xDB];
 
 #[repr(transparent)]
 struct Buffer {
     chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
 }
 
+/// How many bytes `Writer::encode_state` returns.
+pub const STATE_LEN: usize = 4;
+/// The screen is frozen.
+pub const STATE_FROZEN: u8 = 0x01;
+/// The alternate screen is shown.
+pub const STATE_ALTERNATE: u8 = 0x02;
+/// The software cursor is on.
+pub const STATE_SOFTWARE_CURSOR: u8 = 0x04;
+/// The debug overlay holds the top row.
+pub const STATE_DEBUG_OVERLAY: u8 = 0x08;
+/// Output is being dropped because the screen filled up.
+pub const STATE_DISCARDING: u8 = 0x10;
+/// `print!` writes to the screen.
+pub const STATE_VGA_ENABLED: u8 = 0x20;
+/// `print!` output is copied to serial.
+pub const STATE_SERIAL_MIRROR: u8 = 0x40;
+
+/// A copy of the whole screen, taken with `Writer::snapshot`.
+///
+/// Its `Debug` output is the screen as text, one line per row, so a failing
+/// `assert_eq!` between snapshots shows what was actually on screen.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ScreenSnapshot(pub [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT]);
+
+impl fmt::Debug for ScreenSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use core::fmt::Write;
+
+        for row in self.0.iter() {
+            for character in row.iter() {
+                f.write_char(char::from(character.ascii_character))?;
+            }
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+const CRTC_INDEX: u16 = 0x3D4;
+const CRTC_DATA: u16 = 0x3D5;
+const CURSOR_LOCATION_HIGH: u8 = 0x0E;
+const CURSOR_LOCATION_LOW: u8 = 0x0F;
+
+/// Returns the offset of `(row, col)` in VGA memory, counted in cells, or
+/// `None` if it is off screen.
+pub fn linear_index(row: usize, col: usize) -> Option<usize> {
+    if row >= BUFFER_HEIGHT || col >= BUFFER_WIDTH {
+        return None;
+    }
+    Some(row * BUFFER_WIDTH + col)
+}
+
+/// Returns the `(row, col)` of a cell offset, the reverse of `linear_index`.
+///
+/// Offsets past the last cell give a row outside the screen.
+pub fn from_linear(idx: usize) -> (usize, usize) {
+    (idx / BUFFER_WIDTH, idx % BUFFER_WIDTH)
+}
+
+/// Yields the `(row, col)` of every cell that differs between two screens.
+pub fn diff<'a>(
+    front: &'a ScreenSnapshot,
+    back: &'a ScreenSnapshot,
+) -> impl Iterator<Item = (usize, usize)> + 'a {
+    (0..BUFFER_HEIGHT)
+        .flat_map(|row| (0..BUFFER_WIDTH).map(move |col| (row, col)))
+        .filter(move |&(row, col)| front.0[row][col] != back.0[row][col])
+}
+
+/// The lines the writer finished, kept out of `WRITER` so a panic that
+/// happens while the screen is locked can still read them. Only locked with
+/// interrupts off.
+static RECENT: Mutex<RecentLines> = Mutex::new(RecentLines::new());
+
+/// How many lines `recent_lines` can return.
+pub const RECENT_LINES: usize = 16;
+
+/// The last lines the writer finished, oldest first.
+///
+/// A line is recorded when a newline or a wrap ends it, with the bytes as they
+/// were drawn on screen. Scrolling does not affect it.
+#[derive(Clone)]
+pub struct RecentLines {
+    lines: [[u8; BUFFER_WIDTH]; RECENT_LINES],
+    lengths: [usize; RECENT_LINES],
+    oldest: usize,
+    count: usize,
+}
+
+impl RecentLines {
+    const fn new() -> RecentLines {
+        RecentLines {
+            lines: [[b' '; BUFFER_WIDTH]; RECENT_LINES],
+            lengths: [0; RECENT_LINES],
+            oldest: 0,
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, line: &[u8]) {
+        let slot = (self.oldest + self.count) % RECENT_LINES;
+        self.lines[slot][..line.len()].copy_from_slice(line);
+        self.lengths[slot] = line.len();
+        if self.count < RECENT_LINES {
+            self.count += 1;
+        } else {
+            self.oldest = (self.oldest + 1) % RECENT_LINES;
+        }
+    }
+
+    fn newest(&self, n: usize) -> RecentLines {
+        let mut recent = self.clone();
+        let skipped = self.count - n.min(self.count);
+        recent.oldest = (self.oldest + skipped) % RECENT_LINES;
+        recent.count -= skipped;
+        recent
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.count).map(move |i| {
+            let slot = (self.oldest + i) % RECENT_LINES;
+            &self.lines[slot][..self.lengths[slot]]
+        })
+    }
+}
+
+/// A named pair of text colors, applied with `Writer::apply_theme`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub foreground: Color,
+    pub background: Color,
+}
+
+impl Theme {
+    /// The colors the screen starts with.
+    pub const DEFAULT: Theme = Theme {
+        name: "default",
+        foreground: Color::Cyan,
+        background: Color::Black,
+    };
+    pub const HIGH_CONTRAST: Theme = Theme {
+        name: "high contrast",
+        foreground: Color::White,
+        background: Color::Black,
+    };
+    pub const PAPER: Theme = Theme {
+        name: "paper",
+        foreground: Color::Black,
+        background: Color::LightGrey,
+    };
+}
+
+/// What the writer does when text goes past the bottom row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Scroll everything up by one row. This is the default.
+    Scroll,
+    /// Drop all output until the screen is cleared.
+    Discard,
+    /// Continue on the top row, blanking it first.
+    Wrap,
+}
+
+/// Which rows of the screen scroll, as set by `Writer::set_layout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub scroll_top: usize,
+    pub scroll_bottom: usize,
+}
+
+impl Layout {
+    /// Checks that the scroll region is below the first `header_rows` rows
+    /// and ends on screen: `header_rows <= scroll_top <= scroll_bottom`, with
+    /// `scroll_bottom < BUFFER_HEIGHT`.
+    fn validate(self, header_rows: usize) -> Result<(), KernelError> {
+        if header_rows <= self.scroll_top
+            && self.scroll_top <= self.scroll_bottom
+            && self.scroll_bottom < BUFFER_HEIGHT
+        {
+            Ok(())
+        } else {
+            Err(KernelError::InvalidLayout)
+        }
+    }
+}
+
 pub struct Writer {
     column_position: usize,
+    row_position: usize,
     color_code: ColorCode,
+    clear_color: Option<ColorCode>,
+    wrap_column: usize,
+    overflow: Overflow,
+    discarding: bool,
+    frozen: bool,
+    wrapped: bool,
+    top_row: usize,
+    scroll_margin: usize,
+    software_cursor: bool,
+    cursor_cell: Option<(usize, usize, ScreenChar)>,
+    theme: Option<Theme>,
+    dirty: [Option<(usize, usize)>; BUFFER_HEIGHT],
+    alternate: Option<SavedScreen>,
     buffer: &'static mut Buffer,
 }
 
+/// The screen and cursor put aside by `Writer::enter_alternate`.
+struct SavedScreen {
+    snapshot: ScreenSnapshot,
+    row_position: usize,
+    column_position: usize,
+}
+
 impl Writer {
+    /// Writes `s`, drawing each character with the glyph the VGA font has for
+    /// it, as `cp437::from_unicode` gives it. Characters the font lacks are
+    /// drawn as a square.
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                _ => self.write_byte(0xfe),
+        self.wrapped = false;
+        for c in s.chars() {
+            match c {
+                '\n' | '\t' => self.write_byte(c as u8),
+                _ => match cp437::from_unicode(c) {
+                    Some(byte) => self.write_glyph(byte),
+                    None => {
+                        FALLBACK_COUNT.fetch_add(1, Ordering::Relaxed);
+                        self.write_glyph(0xfe)
+                    }
+                },
             }
         }
+        self.update_cursor();
     }
 
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
-            b'\n' => self.new_line(),
-            byte => {
-                if self.column_position >= BUFFER_WIDTH {
+            b'\n' => {
+                if !self.frozen && !self.discarding {
                     self.new_line();
                 }
+            }
+            b'\t' => {
+                for _ in 0..tab_cells(self.column_position, self.wrap_column) {
+                    self.write_byte(b' ');
+                }
+            }
+            byte => self.write_glyph(byte),
+        }
+    }
 
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
-
-                self.buffer.chars[row][col].write(ScreenChar {
-                    ascii_character: byte,
-                    color_code: self.color_code,
-                });
+    /// Draws `byte` at the cursor and moves it on, even for the bytes
+    /// `write_byte` treats as newline and tab.
+    fn write_glyph(&mut self, byte: u8) {
+        if self.frozen || self.discarding {
+            return;
+        }
 
-                self.column_position += 1;
-            }
+        if self.column_position >= self.wrap_column {
+            self.new_line();
+            self.wrapped = true;
         }
+
+        let row = self.row_position;
+        let col = self.column_position;
+
+        self.put(
+            row,
+            col,
+            ScreenChar {
+                ascii_character: byte,
+                color_code: self.color_code,
+            },
+        );
+        CHARS_WRITTEN.fetch_add(1, Ordering::Relaxed);
+
+        self.column_position = self.column_position.saturating_add(1);
+        debug_assert!(self.column_position <= BUFFER_WIDTH);
     }
 
     fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
+        if self.discarding {
+            return;
+        }
+
+        let mut line = [b' '; BUFFER_WIDTH];
+        let len = self.column_position.min(BUFFER_WIDTH);
+        for (col, byte) in line.iter_mut().take(len).enumerate() {
+            *byte = self.buffer.chars[self.row_position][col]
+                .read()
+                .ascii_character;
+        }
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            RECENT.lock().push(&line[..len]);
+        });
+
+        if self.row_position < self.bottom_row() {
+            self.row_position += 1;
+        } else {
+            match self.overflow {
+                Overflow::Scroll => {
+                    self.scroll_up(1);
+                    self.row_position = self.bottom_row();
+                }
+                Overflow::Discard => {
+                    self.discarding = true;
+                    return;
+                }
+                Overflow::Wrap => {
+                    self.row_position = self.top_row;
+                    self.clear_line(self.row_position);
+                }
             }
         }
         self.column_position = 0;
-        self.clear_line(BUFFER_HEIGHT - 1)
     }
 
-    #[cfg(test)]
-    fn clear_screen(&mut self) {
-        for row in 0..BUFFER_HEIGHT {
-            self.clear_line(row);
-        }
-        self.column_position = 0;
+    /// Chooses what happens when text goes past the bottom row.
+    ///
+    /// Changing it resumes output dropped by `Overflow::Discard`.
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+        self.discarding = false;
     }
 
-    fn clear_line(&mut self, row: usize) {
-        let blank = ScreenChar {
-            ascii_character: b' ',
-            color_code: self.color_code,
-        };
+    /// The last row text is written to before the screen scrolls.
+    fn bottom_row(&self) -> usize {
+        BUFFER_HEIGHT - 1 - self.scroll_margin
+    }
 
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+    /// Scrolls when the cursor reaches `rows` rows above the bottom of the
+    /// screen instead of the last row, keeping that many blank rows below it.
+    ///
+    /// The default margin is 0. If the cursor is already below the new bottom
+    /// row, the text scrolls up until it is not. Margins that leave no row to
+    /// scroll are rejected without changing anything.
+    pub fn set_scroll_margin(&mut self, rows: usize) -> Result<(), KernelError> {
+        let scroll_bottom = (BUFFER_HEIGHT - 1)
+            .checked_sub(rows)
+            .ok_or(KernelError::InvalidLayout)?;
+        self.set_layout(Layout {
+            scroll_top: self.top_row,
+            scroll_bottom,
+        })
+    }
+
+    /// The rows that scroll.
+    pub fn layout(&self) -> Layout {
+        Layout {
+            scroll_top: self.top_row,
+            scroll_bottom: self.bottom_row(),
         }
     }
-}
 
-impl fmt::Write for Writer {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.write_string(s);
+    /// Makes only the rows from `layout.scroll_top` to `layout.scroll_bottom`
+    /// scroll. Rows above are a fixed header and rows below are kept blank.
+    ///
+    /// The region must not overlap the debug overlay or leave the screen; an
+    /// invalid layout is rejected without changing anything.
+    pub fn set_layout(&mut self, layout: Layout) -> Result<(), KernelError> {
+        let header_rows = if DEBUG_OVERLAY.load(Ordering::SeqCst) {
+            1
+        } else {
+            0
+        };
+        layout.validate(header_rows)?;
+        self.apply_layout(layout);
         Ok(())
     }
-}
 
-#[macro_export]
-macro_rules! print {
-    ($($arg:tt)*) => ($crate::vga_buffer::_print(format_args!($($arg)*)));
-}
+    fn apply_layout(&mut self, layout: Layout) {
+        let margin = BUFFER_HEIGHT - 1 - layout.scroll_bottom;
+        if self.frozen {
+            self.top_row = layout.scroll_top;
+            self.scroll_margin = margin;
+            return;
+        }
 
-#[macro_export]
-macro_rules! println {
-    () => ($crate::print!("\n"));
-    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
-}
+        if self.row_position > layout.scroll_bottom {
+            self.scroll_up(self.row_position - layout.scroll_bottom);
+            self.row_position = layout.scroll_bottom;
+        }
+        self.top_row = layout.scroll_top;
+        self.scroll_margin = margin;
+        if self.row_position < self.top_row {
+            self.row_position = self.top_row;
+            self.column_position = 0;
+        }
+        for row in layout.scroll_bottom + 1..BUFFER_HEIGHT {
+            self.clear_line(row);
+        }
+        self.update_cursor();
+    }
 
-#[doc(hidden)]
-pub fn _print(args: fmt::Arguments) {
-    use core::fmt::Write;
-    WRITER.lock().write_fmt(args).unwrap();
-}
+    /// Writes `s` only if it fits on the rest of the current row, up to the
+    /// wrap column.
+    ///
+    /// Strings that would wrap, or that contain a newline, are not written at
+    /// all.
+    pub fn write_line_exact(&mut self, s: &str) -> Result<(), KernelError> {
+        let available = self.wrap_column - self.column_position.min(self.wrap_column);
+        match end_column(self.column_position, s, self.wrap_column) {
+            Some(end) if end <= self.wrap_column => {
+                self.write_string(s);
+                Ok(())
+            }
+            end => Err(KernelError::LineTooLong {
+                width: end.map(|end| end - self.column_position),
+                available,
+            }),
+        }
+    }
 
-#[test_case]
-fn test_print_simple_line() {
-    prepare_test("test println... ");
-    println!("simple line print...");
-    serial_println!("[ok]");
-}
+    /// Writes a `key: value` setting on its own line, with the key dimmed and
+    /// the value starting at the same column as other settings.
+    ///
+    /// Keys too long for the value column push the value further right. When
+    /// the wrap column is left of the value column, values start at the wrap
+    /// column instead.
+    pub fn write_kv(&mut self, key: &str, value: &str) {
+        if self.frozen {
+            return;
+        }
+        if self.column_position != 0 {
+            self.new_line();
+        }
 
-#[test_case]
-fn test_print_many_line() {
-    prepare_test("test println many times... ");
-    for _ in 0..200 {
-        println!("single line print...");
+        let color_code = self.color_code;
+        self.color_code = ColorCode::new(Color::DarkGray, color_code.background());
+        self.write_string(key);
+        self.color_code = color_code;
+        self.write_string(": ");
+        let value_column = KV_VALUE_COLUMN.min(self.wrap_column);
+        while !self.discarding && self.column_position < value_column {
+            self.write_byte(b' ');
+        }
+        self.write_string(value);
+        self.write_string("\n");
     }
-    serial_println!("[ok]");
-}
 
-#[test_case]
-fn test_println_output() {
-    prepare_test("test println output... ");
+    /// Writes `s` on its own line in the given colors, with the background
+    /// filled to the end of the row so the line stands out as a banner.
+    pub fn write_banner(&mut self, s: &str, foreground: Color, background: Color) {
+        if self.frozen {
+            return;
+        }
+        if self.column_position != 0 {
+            self.new_line();
+        }
 
-    let s = "long line but single one";
-    println!("{}", s);
+        let color_code = self.color_code;
+        self.color_code = ColorCode::new(foreground, background);
+        self.write_string(s);
+        while !self.discarding
+            && self.column_position != 0
+            && self.column_position < self.wrap_column
+        {
+            self.write_byte(b' ');
+        }
+        self.color_code = color_code;
+        self.write_string("\n");
+    }
 
-    for (i, c) in s.bytes().enumerate() {
-        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
-        assert_eq!(char_screen.ascii_character, c);
+    /// Writes `s` and then moves the cursor to `column` on the current row.
+    ///
+    /// Moving right pads with spaces. If `s` ran past `column` the cursor moves
+    /// back to it, so the next field always starts at `column`. Columns past
+    /// the wrap column stop at the wrap column.
+    pub fn write_then_tab_to(&mut self, s: &str, column: usize) {
+        self.write_string(s);
+        if self.frozen || self.discarding {
+            return;
+        }
+
+        let column = column.min(self.wrap_column);
+        while self.column_position < column {
+            self.write_byte(b' ');
+        }
+        self.column_position = column;
+        self.update_cursor();
     }
 
-    serial_println!("[ok]");
-}
+    /// Writes `value` in decimal, right justified in a field of `width` cells.
+    ///
+    /// The field is padded on the left with '0' when `zero_pad` is set and with
+    /// spaces otherwise. Numbers wider than `width` are written in full.
+    pub fn write_padded_number(&mut self, value: u64, width: usize, zero_pad: bool) {
+        let digits = fixed_buf::decimal(value);
+        let pad = if zero_pad { b'0' } else { b' ' };
+        for _ in digits.len()..width {
+            self.write_byte(pad);
+        }
+        for digit in digits.as_str().bytes() {
+            self.write_byte(digit);
+        }
+        self.update_cursor();
+    }
 
-#[test_case]
-fn test_print_output() {
-    prepare_test("test print output... ");
+    /// Draws a full-width horizontal line on a fresh row and moves to the row
+    /// after it.
+    pub fn hr(&mut self) {
+        self.hr_char(HORIZONTAL_LINE);
+    }
 
-    let s = "long line but single one";
-    print!("{}", s);
+    /// Like `hr`, but draws the line with `byte`, such as `b'='`.
+    pub fn hr_char(&mut self, byte: u8) {
+        if self.frozen {
+            return;
+        }
 
-    for (i, c) in s.bytes().enumerate() {
-        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][i].read();
-        assert_eq!(char_screen.ascii_character, c);
+        if self.column_position != 0 {
+            self.new_line();
+        }
+        for _ in 0..BUFFER_WIDTH {
+            self.write_byte(byte);
+        }
+        self.new_line();
+        self.update_cursor();
     }
 
-    serial_println!("[ok]");
-}
+    /// Erases the character before the cursor and moves the cursor onto it.
+    ///
+    /// Does nothing at the start of a row.
+    pub fn backspace(&mut self) {
+        if self.frozen || self.column_position == 0 {
+            return;
+        }
 
-#[test_case]
-fn test_print_invalid_char_output() {
-    prepare_test("test print invalid char output... ");
+        self.column_position = self.column_position.min(BUFFER_WIDTH) - 1;
+        let blank = self.blank();
+        self.put(self.row_position, self.column_position, blank);
+        self.update_cursor();
+    }
 
-    let s = "áçãó";
-    print!("{}", s);
+    /// The column text wraps at, `BUFFER_WIDTH` unless set otherwise.
+    pub fn wrap_column(&self) -> usize {
+        self.wrap_column
+    }
 
-    for col in 0..8 {
-        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][col].read();
-        assert_eq!(char_screen.ascii_character, 0xfe);
+    /// Makes text wrap to the next row once it reaches `col`, for narrow text
+    /// panes. `col` is clamped to `1..=BUFFER_WIDTH`.
+    pub fn set_wrap_column(&mut self, col: usize) {
+        self.wrap_column = col.clamp(1, BUFFER_WIDTH);
     }
 
-    for col in 8..BUFFER_WIDTH {
-        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][col].read();
-        assert_eq!(char::from(char_screen.ascii_character), char::from(' '));
+    /// Whether the last `write_string` ran past the end of a row and had to
+    /// continue on the next one.
+    pub fn last_write_wrapped(&self) -> bool {
+        self.wrapped
     }
 
-    serial_println!("[ok]");
-}
+    /// Moves the cursor to the top left corner of the screen, below the debug
+    /// overlay if it is shown.
+    pub fn home(&mut self) {
+        self.row_position = self.top_row;
+        self.column_position = 0;
+        self.update_cursor();
+    }
 
-#[test_case]
-fn test_print_with_line_break_output() {
-    prepare_test("test print with line break output... ");
-    WRITER.lock().clear_screen();
+    /// Moves the hardware cursor to where the next character will be written.
+    ///
+    /// The string writing methods do this themselves; `write_byte` does not,
+    /// so callers writing byte by byte call this once they are done.
+    pub fn update_cursor(&mut self) {
+        self.hide_software_cursor();
 
-    let s1 = "first line";
-    let s2 = "second line";
-    print!("{}\n{}", s1, s2);
+        let col = self.column_position.min(BUFFER_WIDTH - 1);
+        let position = linear_index(self.row_position, col).unwrap() as u16;
 
-    for (i, c) in s1.bytes().enumerate() {
-        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
-        assert_eq!(
-            char::from(char_screen.ascii_character),
-            char::from(c),
-            "failed on position {}",
-            i
-        );
+        let mut index = unsafe { Port::new(CRTC_INDEX) };
+        let mut data = unsafe { Port::new(CRTC_DATA) };
+        index.write(CURSOR_LOCATION_LOW);
+        data.write((position & 0xff) as u8);
+        index.write(CURSOR_LOCATION_HIGH);
+        data.write((position >> 8) as u8);
     }
 
-    for (i, c) in s2.bytes().enumerate() {
-        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][i].read();
-        assert_eq!(
-            char::from(char_screen.ascii_character),
-            char::from(c),
-            "failed on position {}",
-            i
-        );
+    /// Moves the rows of the scroll region up by `rows` and blanks the rows
+    /// freed at its bottom, leaving the header rows and the scroll margin
+    /// alone. The cursor does not move.
+    ///
+    /// Scrolling by the height of the region or more blanks all of it.
+    ///
+    /// This takes `&mut self`, so it always runs under the `WRITER` lock and
+    /// nothing else can write to the buffer until every row has been copied.
+    /// `_print` disables interrupts while holding the lock, so an interrupt
+    /// handler printing mid-scroll waits instead of deadlocking or tearing it.
+    pub fn scroll_up(&mut self, rows: usize) {
+        if self.frozen {
+            return;
+        }
+
+        let (top, bottom) = (self.top_row, self.bottom_row());
+        let rows = rows.min(bottom + 1 - top);
+        SCROLL_COUNT.fetch_add(rows as u64, Ordering::Relaxed);
+        self.hide_software_cursor();
+        for row in top + rows..=bottom {
+            for col in 0..BUFFER_WIDTH {
+                let character = self.buffer.chars[row][col].read();
+                self.put(row - rows, col, character);
+            }
+        }
+        for row in bottom + 1 - rows..=bottom {
+            self.clear_line(row);
+        }
     }
 
-    serial_println!("[ok]");
-}
+    /// Sets the colors of the text written from now on.
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.color_code = ColorCode::new(foreground, background);
+        self.theme = None;
+    }
 
-#[test_case]
-fn test_print_break_long_line_output() {
-    prepare_test("test print break long line output... ");
-    WRITER.lock().clear_screen();
+    /// Sets the foreground color of the text written from now on, keeping
+    /// its background.
+    pub fn set_foreground(&mut self, foreground: Color) {
+        self.set_color(foreground, self.color_code.background());
+    }
 
-    let s = "a really really really really really really really really really really really really really really long line";
-    print!("{}", s);
+    /// Sets the background color of the text written from now on, keeping
+    /// its foreground.
+    pub fn set_background(&mut self, background: Color) {
+        self.set_color(self.color_code.foreground(), background);
+    }
 
-    for (i, c) in s[..80].bytes().enumerate() {
-        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
-        assert_eq!(
-            char::from(char_screen.ascii_character),
-            char::from(c),
-            "failed on position {}",
-            i
-        );
+    /// Uses the colors of `theme` for the text written from now on and for
+    /// blanks.
+    pub fn apply_theme(&mut self, theme: Theme) {
+        self.color_code = ColorCode::new(theme.foreground, theme.background);
+        self.clear_color = None;
+        self.theme = Some(theme);
     }
 
-    for (i, c) in s[80..].bytes().enumerate() {
-        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][i].read();
-        assert_eq!(
-            char::from(char_screen.ascii_character),
-            char::from(c),
-            "failed on position {}",
-            i
-        );
+    /// The theme applied last, or `None` if a color was set on its own since.
+    pub fn current_theme(&self) -> Option<Theme> {
+        self.theme
+    }
+
+    /// Sets the colors of the blank cells left by clearing, scrolling and
+    /// erasing, independently of the text color.
+    ///
+    /// By default blanks use the text color.
+    pub fn set_clear_color(&mut self, foreground: Color, background: Color) {
+        self.clear_color = Some(ColorCode::new(foreground, background));
+        self.theme = None;
+    }
+
+    /// The cell used to blank parts of the screen.
+    fn blank(&self) -> ScreenChar {
+        ScreenChar {
+            ascii_character: b' ',
+            color_code: self.clear_color.unwrap_or(self.color_code),
+        }
     }
 
-    serial_println!("[ok]");
+    /// Makes the text written from now on blink, or stop blinking.
+    ///
+    /// This sets the top bit of the attribute byte, which VGA text mode shows
+    /// as blinking by default instead of as a bright background.
+    pub fn set_blink(&mut self, enabled: bool) {
+        let attribute = if enabled {
+            self.color_code.0 | BLINK
+        } else {
+            self.color_code.0 & !BLINK
+        };
+        self.color_code = ColorCode(attribute);
+    }
+
+    /// Draws `text` in the text color from the start of `row`, without moving
+    /// the cursor.
+    fn draw_text(&mut self, row: usize, text: &str) {
+        let color_code = self.color_code;
+        for (col, c) in text.chars().take(BUFFER_WIDTH).enumerate() {
+            let ascii_character = cp437::from_unicode(c).unwrap_or(0xfe);
+            self.put(
+                row,
+                col,
+                ScreenChar {
+                    ascii_character,
+                    color_code,
+                },
+            );
+        }
+    }
+
+    /// Turns the software cursor on or off.
+    ///
+    /// The software cursor shows the cursor position by swapping the colors of
+    /// the cell there each time `blink_cursor` is called, for screens where the
+    /// hardware cursor is unavailable.
+    pub fn set_software_cursor(&mut self, enabled: bool) {
+        if !enabled {
+            self.hide_software_cursor();
+        }
+        self.software_cursor = enabled;
+    }
+
+    /// Shows or hides the software cursor, whichever it is not doing now.
+    ///
+    /// Meant to be called from a timer about four times a second, so the
+    /// cursor blinks at 2Hz. Does nothing unless the software cursor is on.
+    pub fn blink_cursor(&mut self) {
+        if !self.software_cursor || self.frozen {
+            return;
+        }
+        if self.cursor_cell.is_some() {
+            self.hide_software_cursor();
+            return;
+        }
+
+        let row = self.row_position;
+        let col = self.column_position.min(BUFFER_WIDTH - 1);
+        let cell = self.buffer.chars[row][col].read();
+        self.put(row, col, inverted(cell));
+        self.cursor_cell = Some((row, col, cell));
+    }
+
+    /// Puts back the cell under the software cursor, unless something wrote
+    /// over it since.
+    fn hide_software_cursor(&mut self) {
+        if let Some((row, col, cell)) = self.cursor_cell.take() {
+            if self.buffer.chars[row][col].read() == inverted(cell) {
+                self.put(row, col, cell);
+            }
+        }
+    }
+
+    /// Freezes or unfreezes the screen.
+    ///
+    /// While frozen every method that changes the screen does nothing, so its
+    /// contents stay as they were even if other code keeps printing. Output
+    /// written while frozen is lost.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    /// Prints the writer's configuration over serial.
+    pub fn describe(&self) {
+        struct Serial;
+
+        impl fmt::Write for Serial {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                crate::serial_print!("{}", s);
+                Ok(())
+            }
+        }
+
+        self.describe_to(&mut Serial).unwrap();
+    }
+
+    /// Writes the writer's configuration to `out`, one setting per line.
+    pub fn describe_to(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+        let enabled = |on: bool| if on { "enabled" } else { "disabled" };
+
+        writeln!(out, "Writer state:")?;
+        writeln!(
+            out,
+            "  color:         {:?} on {:?}",
+            self.color_code.foreground(),
+            self.color_code.background()
+        )?;
+        writeln!(
+            out,
+            "  cursor:        row {}, column {}",
+            self.row_position, self.column_position
+        )?;
+        writeln!(out, "  tab width:     {}", TAB_WIDTH)?;
+        writeln!(out, "  wrap column:   {}", self.wrap_column)?;
+        writeln!(out, "  overflow:      {:?}", self.overflow)?;
+        writeln!(
+            out,
+            "  scroll region: rows {} to {}",
+            self.top_row,
+            self.bottom_row()
+        )?;
+        writeln!(out, "  scroll margin: {}", self.scroll_margin)?;
+        writeln!(out, "  frozen:        {}", self.frozen)?;
+        writeln!(
+            out,
+            "  vga output:    {}",
+            enabled(ENABLED.load(Ordering::SeqCst))
+        )?;
+        writeln!(
+            out,
+            "  serial mirror: {}",
+            enabled(SERIAL_MIRROR.load(Ordering::SeqCst))
+        )
+    }
+
+    /// Packs the cursor position, color and mode flags into bytes, for the
+    /// serial state probe.
+    ///
+    /// The bytes are the cursor row, the cursor column, the attribute byte of
+    /// the text color and the `STATE_*` flags that are set.
+    pub fn encode_state(&self) -> [u8; STATE_LEN] {
+        let mut flags = 0;
+        for &(set, flag) in [
+            (self.frozen, STATE_FROZEN),
+            (self.alternate.is_some(), STATE_ALTERNATE),
+            (self.software_cursor, STATE_SOFTWARE_CURSOR),
+            (DEBUG_OVERLAY.load(Ordering::SeqCst), STATE_DEBUG_OVERLAY),
+            (self.discarding, STATE_DISCARDING),
+            (ENABLED.load(Ordering::SeqCst), STATE_VGA_ENABLED),
+            (SERIAL_MIRROR.load(Ordering::SeqCst), STATE_SERIAL_MIRROR),
+        ]
+        .iter()
+        {
+            if set {
+                flags |= flag;
+            }
+        }
+
+        [
+            self.row_position as u8,
+            self.column_position as u8,
+            self.color_code.attribute(),
+            flags,
+        ]
+    }
+
+    /// Writes `cell` to `(row, col)` and widens the dirty range of the row.
+    fn put(&mut self, row: usize, col: usize, cell: ScreenChar) {
+        self.buffer.chars[row][col].write(cell);
+        self.dirty[row] = Some(match self.dirty[row] {
+            Some((first, last)) => (first.min(col), last.max(col)),
+            None => (col, col),
+        });
+    }
+
+    /// The first and last column written on `row` since the last `present`
+    /// or `clear_screen`, or `None` if nothing was written there.
+    ///
+    /// Rows outside the screen are never dirty.
+    pub fn dirty_range(&self, row: usize) -> Option<(usize, usize)> {
+        self.dirty.get(row).copied().flatten()
+    }
+
+    /// Reads the cell at `(row, col)`, or `None` if it is off screen.
+    pub fn read_char(&self, row: usize, col: usize) -> Option<ScreenChar> {
+        self.try_read_char(row, col).ok()
+    }
+
+    /// Like `read_char`, but says which cell was off screen.
+    pub fn try_read_char(&self, row: usize, col: usize) -> Result<ScreenChar, KernelError> {
+        if row >= BUFFER_HEIGHT || col >= BUFFER_WIDTH {
+            return Err(KernelError::OutOfBounds { row, col });
+        }
+        Ok(self.buffer.chars[row][col].read())
+    }
+
+    /// How many cells on the screen hold something other than a space.
+    pub fn occupied_cells(&self) -> usize {
+        (0..BUFFER_HEIGHT).map(|row| self.row_occupied(row)).sum()
+    }
+
+    /// How many cells on `row` hold something other than a space. Rows
+    /// outside the screen have none.
+    pub fn row_occupied(&self, row: usize) -> usize {
+        match self.buffer.chars.get(row) {
+            Some(line) => line
+                .iter()
+                .filter(|cell| cell.read().ascii_character != b' ')
+                .count(),
+            None => 0,
+        }
+    }
+
+    /// Copies the current screen contents.
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        let mut snapshot = ScreenSnapshot([[blank; BUFFER_WIDTH]; BUFFER_HEIGHT]);
+        for (row, line) in snapshot.0.iter_mut().enumerate() {
+            for (col, character) in line.iter_mut().enumerate() {
+                *character = self.buffer.chars[row][col].read();
+            }
+        }
+        snapshot
+    }
+
+    /// Makes the screen show `back`, writing only the cells that changed.
+    pub fn present(&mut self, back: &ScreenSnapshot) {
+        if self.frozen {
+            return;
+        }
+
+        let front = self.snapshot();
+        for (row, col) in diff(&front, back) {
+            self.buffer.chars[row][col].write(back.0[row][col]);
+        }
+        self.dirty = [None; BUFFER_HEIGHT];
+    }
+
+    /// Switches to a blank alternate screen, saving the current one.
+    ///
+    /// Meant for full-screen programs that should not clobber earlier output.
+    /// Only one level is supported: calling this while already on the
+    /// alternate screen does nothing.
+    pub fn enter_alternate(&mut self) {
+        if self.alternate.is_some() {
+            return;
+        }
+
+        // Otherwise the inverted cursor cell is saved and restored as text.
+        self.hide_software_cursor();
+        self.alternate = Some(SavedScreen {
+            snapshot: self.snapshot(),
+            row_position: self.row_position,
+            column_position: self.column_position,
+        });
+        self.clear_screen();
+        self.home();
+    }
+
+    /// Restores the screen and cursor saved by `enter_alternate`.
+    pub fn leave_alternate(&mut self) {
+        if let Some(saved) = self.alternate.take() {
+            self.present(&saved.snapshot);
+            self.row_position = saved.row_position;
+            self.column_position = saved.column_position;
+            self.update_cursor();
+        }
+    }
+
+    /// Changes the colors of the cell at `(row, col)`, keeping its character.
+    ///
+    /// Cells off screen are ignored and the cursor does not move.
+    pub fn set_cell_color(&mut self, row: usize, col: usize, foreground: Color, background: Color) {
+        if self.frozen {
+            return;
+        }
+
+        if let Some(mut cell) = self.read_char(row, col) {
+            cell.color_code = ColorCode::new(foreground, background);
+            self.put(row, col, cell);
+        }
+    }
+
+    /// Writes `ch` in the text color at cell `offset` of VGA memory, counted
+    /// the way `linear_index` counts them.
+    ///
+    /// Offsets past the last cell are ignored and the cursor does not move.
+    pub fn write_at_offset(&mut self, offset: usize, ch: u8) {
+        if self.frozen || offset >= BUFFER_HEIGHT * BUFFER_WIDTH {
+            return;
+        }
+
+        let (row, col) = from_linear(offset);
+        self.put(
+            row,
+            col,
+            ScreenChar {
+                ascii_character: ch,
+                color_code: self.color_code,
+            },
+        );
+    }
+
+    /// Writes `cells` to `row` starting at the first column.
+    ///
+    /// Each cell keeps its own colors and the cursor does not move. Cells past
+    /// the end of the row are dropped and rows outside the screen are ignored.
+    pub fn write_row(&mut self, row: usize, cells: &[Cell]) {
+        if self.frozen || row >= BUFFER_HEIGHT {
+            return;
+        }
+
+        for (col, cell) in cells.iter().take(BUFFER_WIDTH).enumerate() {
+            self.put(row, col, *cell);
+        }
+    }
+
+    /// Draws `cells` as a block `width` cells wide with its top left corner at
+    /// `(top, left)`, skipping spaces so the screen shows through them.
+    ///
+    /// `cells` holds the block row by row. Parts of the block off screen are
+    /// dropped and the cursor does not move.
+    pub fn blit_transparent(&mut self, top: usize, left: usize, cells: &[Cell], width: usize) {
+        if self.frozen || width == 0 {
+            return;
+        }
+
+        for (i, line) in cells.chunks(width).enumerate() {
+            let row = top + i;
+            if row >= BUFFER_HEIGHT {
+                break;
+            }
+            for (j, cell) in line.iter().enumerate() {
+                let col = left + j;
+                if col >= BUFFER_WIDTH {
+                    break;
+                }
+                if cell.ascii_character != b' ' {
+                    self.put(row, col, *cell);
+                }
+            }
+        }
+    }
+
+    /// Draws `values` as a bar chart along `row`, one cell per value.
+    ///
+    /// Each value from 0 (empty) to 8 (full block) picks one of the CP437 shade
+    /// glyphs; larger values are drawn as 8. Like `write_row`, the cursor does
+    /// not move and values past the end of the row are dropped.
+    pub fn sparkline(&mut self, row: usize, values: &[u8]) {
+        if self.frozen || row >= BUFFER_HEIGHT {
+            return;
+        }
+
+        for (col, &value) in values.iter().take(BUFFER_WIDTH).enumerate() {
+            self.put(
+                row,
+                col,
+                ScreenChar {
+                    ascii_character: SPARKLINE_GLYPHS[usize::from(value.min(8))],
+                    color_code: self.color_code,
+                },
+            );
+        }
+    }
+
+    /// Draws `template`, a screen written as plain text, from the top left
+    /// corner in the current color. The template starts on row 0 even when
+    /// the rows above the scroll region are a fixed header.
+    ///
+    /// Each line of `template` fills one row: short lines are padded with
+    /// spaces and long ones cut at the screen width. Rows past the end of the
+    /// template are left alone and the cursor does not move.
+    pub fn load_template(&mut self, template: &str) {
+        if self.frozen {
+            return;
+        }
+
+        for (row, line) in (0..BUFFER_HEIGHT).zip(template.lines()) {
+            let mut chars = line.chars();
+            for col in 0..BUFFER_WIDTH {
+                let ascii_character = match chars.next() {
+                    Some(c) => cp437::from_unicode(c).unwrap_or(0xfe),
+                    None => b' ',
+                };
+                self.put(
+                    row,
+                    col,
+                    ScreenChar {
+                        ascii_character,
+                        color_code: self.color_code,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Changes the colors of every cell in `row`, keeping its characters.
+    ///
+    /// Rows outside the screen are ignored.
+    pub fn recolor_line(&mut self, row: usize, foreground: Color, background: Color) {
+        if self.frozen || row >= BUFFER_HEIGHT {
+            return;
+        }
+
+        let color_code = ColorCode::new(foreground, background);
+        for col in 0..BUFFER_WIDTH {
+            let mut character = self.buffer.chars[row][col].read();
+            character.color_code = color_code;
+            self.put(row, col, character);
+        }
+    }
+
+    /// Blanks the screen and moves the cursor to the start of the bottom row,
+    /// above the scroll margin. With `Overflow::Discard` or `Overflow::Wrap`
+    /// the cursor goes to the top row instead, so the whole screen fills
+    /// before output is dropped or wraps.
+    pub fn clear_screen(&mut self) {
+        if self.frozen {
+            return;
+        }
+
+        for row in self.top_row..BUFFER_HEIGHT {
+            self.clear_line(row);
+        }
+        self.discarding = false;
+        self.dirty = [None; BUFFER_HEIGHT];
+        self.row_position = match self.overflow {
+            Overflow::Scroll => self.bottom_row(),
+            Overflow::Discard | Overflow::Wrap => self.top_row,
+        };
+        self.column_position = 0;
+        self.update_cursor();
+    }
+
+    /// Blanks the row the cursor is on and moves the cursor to its start.
+    pub fn clear_current_line(&mut self) {
+        if self.frozen {
+            return;
+        }
+
+        self.clear_line(self.row_position);
+        self.column_position = 0;
+        self.update_cursor();
+    }
+
+    /// Redraws the debug overlay on the top row.
+    fn draw_debug_overlay(&mut self) {
+        use core::fmt::Write;
+
+        if self.frozen {
+            return;
+        }
+
+        let mut line = FixedBuf::<BUFFER_WIDTH>::new();
+        let _ = write!(
+            line,
+            "chars {}  scrolls {}  contended {}  panics {}  ticks {}",
+            CHARS_WRITTEN.load(Ordering::Relaxed),
+            SCROLL_COUNT.load(Ordering::Relaxed),
+            LOCK_CONTENTIONS.load(Ordering::Relaxed),
+            crate::panic::count(),
+            crate::time::ticks()
+        );
+
+        let color_code = ColorCode::new(Color::Black, Color::LightGrey);
+        let text = line.as_str().as_bytes();
+        for col in 0..BUFFER_WIDTH {
+            self.put(
+                0,
+                col,
+                ScreenChar {
+                    ascii_character: text.get(col).copied().unwrap_or(b' '),
+                    color_code,
+                },
+            );
+        }
+    }
+
+    fn clear_line(&mut self, row: usize) {
+        let blank = self.blank();
+
+        for col in 0..BUFFER_WIDTH {
+            self.put(row, col, blank);
+        }
+    }
+}
+
+/// `cell` with its foreground and background colors swapped.
+fn inverted(cell: ScreenChar) -> ScreenChar {
+    let attribute = cell.color_code.attribute();
+    ScreenChar {
+        ascii_character: cell.ascii_character,
+        color_code: ColorCode(attribute.rotate_right(4)),
+    }
+}
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::vga_buffer::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+/// Returns how many cells `write_string` would fill when printing `s` with
+/// text wrapping at `wrap_column`.
+///
+/// Newlines take no cells, tabs take the spaces up to the next tab stop and
+/// every other character, even one the font has no glyph for, takes one.
+/// Tab stops are computed as if `s` started at the beginning of a line.
+pub fn display_width(s: &str, wrap_column: usize) -> usize {
+    let mut column = 0;
+    let mut width = 0;
+
+    for c in s.chars() {
+        if c == '\n' {
+            column = 0;
+            continue;
+        }
+        if column >= wrap_column {
+            column = 0;
+        }
+
+        let cells = match c {
+            '\t' => tab_cells(column, wrap_column),
+            _ => 1,
+        };
+        column += cells;
+        width += cells;
+    }
+
+    width
+}
+
+/// How many spaces a tab written at `column` takes on rows that wrap at
+/// `wrap_column`.
+///
+/// Tab stops are counted from the start of the row the tab lands on, so a tab
+/// at or past the wrap column starts the next row, and a tab never runs past
+/// the wrap column.
+fn tab_cells(column: usize, wrap_column: usize) -> usize {
+    let column = if column >= wrap_column { 0 } else { column };
+    ((column / TAB_WIDTH + 1) * TAB_WIDTH).min(wrap_column) - column
+}
+
+/// Reads the hardware cursor position back from the VGA controller, as a cell
+/// offset.
+pub fn hardware_cursor() -> usize {
+    let mut index = unsafe { Port::new(CRTC_INDEX) };
+    let mut data = unsafe { Port::<u8>::new(CRTC_DATA) };
+    index.write(CURSOR_LOCATION_HIGH);
+    let high = data.read() as usize;
+    index.write(CURSOR_LOCATION_LOW);
+    let low = data.read() as usize;
+    high << 8 | low
+}
+
+/// Returns whether `s` fits on one empty row of `width` cells without
+/// wrapping, as measured by `display_width`.
+///
+/// Pass `Writer::wrap_column` to check against the screen as it is set up.
+pub fn assert_fits(s: &str, width: usize) -> bool {
+    !s.contains('\n') && display_width(s, width) <= width
+}
+
+/// Returns the column after writing `s` from `start` on an endless row, or
+/// `None` if `s` contains a newline. Tabs stop at `wrap_column`, as they do
+/// on screen.
+fn end_column(start: usize, s: &str, wrap_column: usize) -> Option<usize> {
+    let mut column = start;
+    for c in s.chars() {
+        column += match c {
+            '\n' => return None,
+            '\t' => tab_cells(column, wrap_column),
+            _ => 1,
+        };
+    }
+    Some(column)
+}
+
+/// Clears the screen so output starts at the top left corner.
+pub fn init() {
+    let mut writer = WRITER.lock();
+    writer.clear_screen();
+    writer.home();
+}
+
+/// Returns up to `n` of the most recently finished lines, oldest first.
+pub fn recent_lines(n: usize) -> RecentLines {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| RECENT.lock().newest(n))
+}
+
+/// Like `recent_lines`, but returns `None` instead of waiting if a line is
+/// being recorded, so it is safe to call from a panic handler. It does not
+/// need the screen lock.
+pub fn try_recent_lines(n: usize) -> Option<RecentLines> {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| RECENT.try_lock().map(|recent| recent.newest(n)))
+}
+
+/// Fills the scroll region with a pattern that differs for every row and
+/// column, scrolls it up by `rows` and checks that every surviving row moved
+/// up by exactly `rows` and that the rows freed at the bottom are blank.
+///
+/// Returns the first cell that is wrong. Meant for tests: the screen is left
+/// holding the pattern.
+pub fn verify_scroll_integrity(rows: usize) -> Result<(), (usize, usize)> {
+    use x86_64::instructions::interrupts;
+
+    let pattern = |row: usize, col: usize| ScreenChar {
+        ascii_character: b'A' + row as u8,
+        color_code: ColorCode(col as u8),
+    };
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        let (top, bottom) = (writer.top_row, writer.bottom_row());
+        for row in top..=bottom {
+            for col in 0..BUFFER_WIDTH {
+                writer.put(row, col, pattern(row, col));
+            }
+        }
+
+        writer.scroll_up(rows);
+
+        let blank = writer.blank();
+        for row in top..=bottom {
+            for col in 0..BUFFER_WIDTH {
+                let expected = if row + rows <= bottom {
+                    pattern(row + rows, col)
+                } else {
+                    blank
+                };
+                if writer.buffer.chars[row][col].read() != expected {
+                    return Err((row, col));
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+static SCROLL_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// How many rows the screen has scrolled since boot. Scrolling several rows
+/// at once counts each of them.
+pub fn scroll_count() -> u64 {
+    SCROLL_COUNT.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+fn reset_scroll_count() {
+    SCROLL_COUNT.store(0, Ordering::Relaxed);
+}
+
+static FALLBACK_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// How many characters `write_string` has drawn as the fallback square
+/// because the VGA font has no glyph for them.
+pub fn fallback_count() -> u64 {
+    FALLBACK_COUNT.load(Ordering::Relaxed)
+}
+
+pub fn reset_fallback_count() {
+    FALLBACK_COUNT.store(0, Ordering::Relaxed);
+}
+
+static CHARS_WRITTEN: AtomicU64 = AtomicU64::new(0);
+static LOCK_CONTENTIONS: AtomicU64 = AtomicU64::new(0);
+static DEBUG_OVERLAY: AtomicBool = AtomicBool::new(false);
+
+/// Redraws the debug overlay, if it is shown. `time::tick` calls this.
+///
+/// It runs in the timer interrupt, so if the writer is locked the overlay is
+/// left for the next tick or `print!` to redraw.
+pub fn refresh_debug_overlay() {
+    use x86_64::instructions::interrupts;
+
+    if !DEBUG_OVERLAY.load(Ordering::SeqCst) {
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        if let Some(mut writer) = WRITER.try_lock() {
+            writer.draw_debug_overlay();
+        }
+    });
+}
+
+/// Draws `text` at the start of `row`, blinking for `times` on and off
+/// cycles of half a second, and then leaves it there without blinking.
+///
+/// Halts between changes, so it needs the timer interrupt to be calling
+/// `time::tick`. The writer is only locked while the text is drawn, so other
+/// code can keep printing meanwhile. The cursor does not move and text past
+/// the end of the row is dropped.
+pub fn flash_message(row: usize, text: &str, times: u32) {
+    flash_message_with_idle(row, text, times, x86_64::instructions::hlt);
+}
+
+/// Like `flash_message`, but calls `idle` instead of halting while it waits.
+pub fn flash_message_with_idle(row: usize, text: &str, times: u32, idle: fn()) {
+    if row >= BUFFER_HEIGHT {
+        return;
+    }
+
+    let mut limiter = FrameLimiter::with_idle(FLASH_PHASES_PER_SECOND, idle);
+    for _ in 0..times {
+        draw_flash(row, text, true);
+        limiter.wait();
+        draw_flash(row, text, false);
+        limiter.wait();
+    }
+    draw_flash(row, text, false);
+}
+
+/// Draws one phase of `flash_message` in the text color, with or without the
+/// blink attribute.
+fn draw_flash(row: usize, text: &str, blink: bool) {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        if writer.frozen {
+            return;
+        }
+        let color_code = writer.color_code;
+        writer.set_blink(blink);
+        writer.draw_text(row, text);
+        writer.color_code = color_code;
+    });
+}
+
+/// Shows or hides a line of counters on the top row of the screen.
+///
+/// The overlay lists the characters written, the scrolls, how often `print!`
+/// had to wait for the screen lock, the panics and the timer ticks so far.
+/// While it is shown the top row is kept out of scrolling and clearing, and
+/// the overlay is redrawn after every `print!` and every `time::tick`.
+/// Showing it fails if the layout leaves the top row as the only one that
+/// scrolls.
+pub fn set_debug_overlay(enabled: bool) -> Result<(), KernelError> {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        let mut layout = writer.layout();
+        if enabled {
+            layout.scroll_top = layout.scroll_top.max(1);
+            layout.validate(1)?;
+            DEBUG_OVERLAY.store(true, Ordering::SeqCst);
+            writer.apply_layout(layout);
+            writer.draw_debug_overlay();
+        } else {
+            DEBUG_OVERLAY.store(false, Ordering::SeqCst);
+            if layout.scroll_top == 1 {
+                layout.scroll_top = 0;
+                writer.apply_layout(layout);
+            }
+            if !writer.frozen {
+                writer.clear_line(0);
+            }
+        }
+        Ok(())
+    })
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static SERIAL_MIRROR: AtomicBool = AtomicBool::new(false);
+
+/// Turns writing `print!` output to the VGA buffer on or off.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Sends a copy of all `print!` output to the serial port when enabled.
+pub fn set_serial_mirror(enabled: bool) {
+    SERIAL_MIRROR.store(enabled, Ordering::SeqCst);
+}
+
+/// Where `print!` output goes while `capture_into` runs: the caller's
+/// `FixedBuf` and the function that writes to a buffer of its size.
+struct Capture {
+    buf: *mut (),
+    write: fn(*mut (), &str),
+}
+
+// The pointer is only used while `capture_into` holds the borrow of the buffer.
+unsafe impl Send for Capture {}
+
+impl fmt::Write for Capture {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        (self.write)(self.buf, s);
+        Ok(())
+    }
+}
+
+fn write_capture<const N: usize>(buf: *mut (), s: &str) {
+    use core::fmt::Write;
+
+    let buf = unsafe { &mut *(buf as *mut FixedBuf<N>) };
+    let _ = buf.write_str(s);
+}
+
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+/// Runs `f` with `print!` output added to `buf` instead of the screen and
+/// the serial mirror.
+///
+/// Output that does not fit in `buf` is dropped. Captures can be nested; the
+/// outer one resumes when the inner one returns.
+pub fn capture_into<const N: usize, F: FnOnce()>(buf: &mut FixedBuf<N>, f: F) {
+    use x86_64::instructions::interrupts;
+
+    let capture = Capture {
+        buf: buf as *mut FixedBuf<N> as *mut (),
+        write: write_capture::<N>,
+    };
+    let outer = interrupts::without_interrupts(|| CAPTURE.lock().replace(capture));
+    f();
+    interrupts::without_interrupts(|| *CAPTURE.lock() = outer);
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    let captured = interrupts::without_interrupts(|| match CAPTURE.lock().as_mut() {
+        Some(capture) => {
+            let _ = capture.write_fmt(args);
+            true
+        }
+        None => false,
+    });
+    if captured {
+        return;
+    }
+
+    if SERIAL_MIRROR.load(Ordering::SeqCst) {
+        crate::serial::_print(args);
+    }
+
+    if ENABLED.load(Ordering::SeqCst) {
+        interrupts::without_interrupts(|| {
+            let mut writer = match WRITER.try_lock() {
+                Some(writer) => writer,
+                None => {
+                    LOCK_CONTENTIONS.fetch_add(1, Ordering::Relaxed);
+                    WRITER.lock()
+                }
+            };
+            writer.write_fmt(args).unwrap();
+            if DEBUG_OVERLAY.load(Ordering::SeqCst) {
+                writer.draw_debug_overlay();
+            }
+        });
+    }
+}
+
+#[test_case]
+fn test_print_simple_line() {
+    prepare_test();
+    println!("simple line print...");
+}
+
+#[test_case]
+fn test_print_many_line() {
+    prepare_test();
+    for _ in 0..200 {
+        println!("single line print...");
+    }
+}
+
+#[test_case]
+fn test_println_output() {
+    prepare_test();
+
+    let s = "long line but single one";
+    println!("{}", s);
+
+    for (i, c) in s.bytes().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
+        assert_eq!(char_screen.ascii_character, c);
+    }
+}
+
+#[test_case]
+fn test_print_output() {
+    prepare_test();
+
+    let s = "long line but single one";
+    print!("{}", s);
+
+    for (i, c) in s.bytes().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][i].read();
+        assert_eq!(char_screen.ascii_character, c);
+    }
+}
+
+#[test_case]
+fn test_print_invalid_char_output() {
+    prepare_test();
+
+    let s = "áçãó";
+    print!("{}", s);
+
+    // 'ã' is not in Code Page 437, so it falls back to the square.
+    for (col, &glyph) in [0xa0, 0x87, 0xfe, 0xa2].iter().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][col].read();
+        assert_eq!(char_screen.ascii_character, glyph);
+    }
+
+    for col in 4..BUFFER_WIDTH {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][col].read();
+        assert_eq!(char::from(char_screen.ascii_character), char::from(' '));
+    }
+}
+
+#[test_case]
+fn test_print_box_drawing_output() {
+    prepare_test();
+
+    // '○' and '◙' share their bytes with tab and newline.
+    print!("┌─┐○◙");
+
+    for (col, &glyph) in [0xda, 0xc4, 0xbf, 0x09, 0x0a].iter().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][col].read();
+        assert_eq!(char_screen.ascii_character, glyph);
+    }
+    assert_eq!(WRITER.lock().column_position, 5);
+}
+
+#[test_case]
+fn test_print_with_line_break_output() {
+    prepare_test();
+    WRITER.lock().clear_screen();
+
+    let s1 = "first line";
+    let s2 = "second line";
+    print!("{}\n{}", s1, s2);
+
+    for (i, c) in s1.bytes().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
+        assert_eq!(
+            char::from(char_screen.ascii_character),
+            char::from(c),
+            "failed on position {}",
+            i
+        );
+    }
+
+    for (i, c) in s2.bytes().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][i].read();
+        assert_eq!(
+            char::from(char_screen.ascii_character),
+            char::from(c),
+            "failed on position {}",
+            i
+        );
+    }
+}
+
+#[test_case]
+fn test_print_break_long_line_output() {
+    prepare_test();
+    WRITER.lock().clear_screen();
+
+    let s = "a really really really really really really really really really really really really really really long line";
+    print!("{}", s);
+
+    for (i, c) in s[..80].bytes().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
+        assert_eq!(
+            char::from(char_screen.ascii_character),
+            char::from(c),
+            "failed on position {}",
+            i
+        );
+    }
+
+    for (i, c) in s[80..].bytes().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][i].read();
+        assert_eq!(
+            char::from(char_screen.ascii_character),
+            char::from(c),
+            "failed on position {}",
+            i
+        );
+    }
+}
+
+#[test_case]
+fn test_scroll_keeps_rows_whole() {
+    prepare_test();
+
+    for i in 0..300 {
+        let byte = b'a' + (i % 26) as u8;
+        let mut writer = WRITER.lock();
+        for _ in 0..BUFFER_WIDTH {
+            writer.write_byte(byte);
+        }
+        drop(writer);
+        print!("\n");
+    }
+
+    for row in 0..BUFFER_HEIGHT - 1 {
+        let first = WRITER.lock().buffer.chars[row][0].read();
+        for col in 1..BUFFER_WIDTH {
+            let char_screen = WRITER.lock().buffer.chars[row][col].read();
+            assert_eq!(char_screen, first, "torn row {} at column {}", row, col);
+        }
+    }
+
+    for row in 1..BUFFER_HEIGHT - 1 {
+        let above = WRITER.lock().buffer.chars[row - 1][0].read();
+        let current = WRITER.lock().buffer.chars[row][0].read();
+        let expected = b'a' + (above.ascii_character - b'a' + 1) % 26;
+        assert_eq!(
+            current.ascii_character, expected,
+            "row {} out of order",
+            row
+        );
+    }
+}
+
+#[test_case]
+fn test_recolor_line() {
+    prepare_test();
+
+    let s = "selected item";
+    print!("{}", s);
+    WRITER
+        .lock()
+        .recolor_line(BUFFER_HEIGHT - 1, Color::Black, Color::LightGrey);
+    WRITER
+        .lock()
+        .recolor_line(BUFFER_HEIGHT, Color::Black, Color::LightGrey);
+
+    let expected = ColorCode::new(Color::Black, Color::LightGrey);
+    for (i, c) in s.bytes().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][i].read();
+        assert_eq!(char_screen.ascii_character, c);
+        assert_eq!(char_screen.color_code, expected);
+    }
+
+    for col in s.len()..BUFFER_WIDTH {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][col].read();
+        assert_eq!(char_screen.ascii_character, b' ');
+        assert_eq!(char_screen.color_code, expected);
+    }
+
+    let above = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][0].read();
+    assert_eq!(above.color_code, ColorCode::new(Color::Cyan, Color::Black));
+}
+
+#[test_case]
+fn test_snapshot_debug_output() {
+    use core::fmt::Write;
+
+    prepare_test();
+
+    let first = WRITER.lock().snapshot();
+    assert_eq!(first, WRITER.lock().snapshot());
+
+    print!("snapshot");
+    let second = WRITER.lock().snapshot();
+    assert_ne!(first, second);
+
+    let mut output = FixedBuf::<2048>::new();
+    write!(output, "{:?}", second).unwrap();
+    let text = output.as_str();
+
+    assert_eq!(text.lines().count(), BUFFER_HEIGHT);
+    for line in text.lines() {
+        assert_eq!(line.len(), BUFFER_WIDTH);
+    }
+    let last = text.lines().last().unwrap();
+    assert!(last.starts_with("snapshot "), "bottom row was {:?}", last);
+}
+
+#[test_case]
+fn test_home_moves_to_top_left() {
+    prepare_test();
+
+    WRITER.lock().home();
+    print!("top");
+
+    for (i, c) in "top".bytes().enumerate() {
+        let char_screen = WRITER.lock().buffer.chars[0][i].read();
+        assert_eq!(char_screen.ascii_character, c);
+    }
+    let bottom = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][0].read();
+    assert_eq!(bottom.ascii_character, b' ');
+
+    println!();
+    print!("next");
+    let next = WRITER.lock().buffer.chars[1][0].read();
+    assert_eq!(next.ascii_character, b'n');
+}
+
+#[test_case]
+fn test_verify_scroll_integrity() {
+    prepare_test();
+    for &rows in [0, 1, 3, BUFFER_HEIGHT - 1, BUFFER_HEIGHT, BUFFER_HEIGHT + 5].iter() {
+        assert_eq!(
+            verify_scroll_integrity(rows),
+            Ok(()),
+            "scrolling {} rows",
+            rows
+        );
+    }
+
+    let region = Layout {
+        scroll_top: 2,
+        scroll_bottom: 20,
+    };
+    WRITER.lock().set_layout(region).unwrap();
+    let scrolled = verify_scroll_integrity(2);
+    let header = WRITER.lock().read_char(1, 0).unwrap();
+    let margin = WRITER.lock().read_char(21, 0).unwrap();
+    WRITER
+        .lock()
+        .set_layout(Layout {
+            scroll_top: 0,
+            scroll_bottom: BUFFER_HEIGHT - 1,
+        })
+        .unwrap();
+    prepare_test();
+
+    assert_eq!(scrolled, Ok(()));
+    assert_eq!(header.ascii_character, b' ');
+    assert_eq!(margin.ascii_character, b' ');
+}
+
+#[test_case]
+fn test_scroll_count() {
+    prepare_test();
+
+    reset_scroll_count();
+    for _ in 0..30 {
+        println!("line");
+    }
+    assert_eq!(scroll_count(), 30);
+
+    WRITER.lock().clear_screen();
+    WRITER.lock().home();
+    reset_scroll_count();
+    for _ in 0..30 {
+        println!("line");
+    }
+    assert_eq!(scroll_count(), 30 - (BUFFER_HEIGHT as u64 - 1));
+}
+
+#[test_case]
+fn test_try_read_char() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.write_string("q");
+
+    assert_eq!(
+        writer
+            .try_read_char(BUFFER_HEIGHT - 1, 0)
+            .map(|cell| cell.ascii_character),
+        Ok(b'q')
+    );
+    assert_eq!(
+        writer.try_read_char(BUFFER_HEIGHT, 3),
+        Err(KernelError::OutOfBounds {
+            row: BUFFER_HEIGHT,
+            col: 3,
+        })
+    );
+    assert_eq!(
+        writer.try_read_char(0, BUFFER_WIDTH),
+        Err(KernelError::OutOfBounds {
+            row: 0,
+            col: BUFFER_WIDTH,
+        })
+    );
+}
+
+#[test_case]
+fn test_set_cell_color() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.write_string("@.");
+    let row = BUFFER_HEIGHT - 1;
+    let neighbour = writer.read_char(row, 1).unwrap();
+
+    writer.set_cell_color(row, 0, Color::Yellow, Color::Red);
+    writer.set_cell_color(BUFFER_HEIGHT, 0, Color::Yellow, Color::Red);
+    writer.set_cell_color(row, BUFFER_WIDTH, Color::Yellow, Color::Red);
+
+    assert_eq!(
+        writer.read_char(row, 0).unwrap(),
+        Cell::new(b'@', Color::Yellow, Color::Red)
+    );
+    assert_eq!(writer.read_char(row, 1).unwrap(), neighbour);
+    assert_eq!(writer.column_position, 2);
+}
+
+#[test_case]
+fn test_write_at_offset() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    let cursor = (writer.row_position, writer.column_position);
+    writer.write_at_offset(0, b'<');
+    writer.write_at_offset(BUFFER_HEIGHT * BUFFER_WIDTH - 1, b'>');
+    writer.write_at_offset(BUFFER_HEIGHT * BUFFER_WIDTH, b'!');
+
+    assert_eq!(writer.read_char(0, 0).unwrap().ascii_character, b'<');
+    assert_eq!(
+        writer
+            .read_char(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1)
+            .unwrap()
+            .ascii_character,
+        b'>'
+    );
+    assert_eq!(writer.occupied_cells(), 2);
+    assert_eq!((writer.row_position, writer.column_position), cursor);
+}
+
+#[test_case]
+fn test_write_row() {
+    prepare_test();
+
+    let red = Cell::new(b'r', Color::Red, Color::Black);
+    let green = Cell::new(b'g', Color::Green, Color::White);
+    let mut cells = [red; BUFFER_WIDTH + 10];
+    for cell in cells.iter_mut().skip(1).step_by(2) {
+        *cell = green;
+    }
+
+    WRITER.lock().write_row(3, &cells);
+
+    for &col in [0, 1, 2, 41, BUFFER_WIDTH - 1].iter() {
+        let char_screen = WRITER.lock().buffer.chars[3][col].read();
+        assert_eq!(char_screen, cells[col], "failed on column {}", col);
+    }
+    let cursor_row = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][0].read();
+    assert_eq!(cursor_row.ascii_character, b' ');
+
+    WRITER.lock().write_row(BUFFER_HEIGHT, &cells);
+}
+
+#[test_case]
+fn test_clear_current_line() {
+    prepare_test();
+
+    println!("kept");
+    print!("> half typed command");
+    WRITER.lock().clear_current_line();
+
+    for col in 0..BUFFER_WIDTH {
+        let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][col].read();
+        assert_eq!(
+            char_screen.ascii_character, b' ',
+            "failed on column {}",
+            col
+        );
+    }
+    let above = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][0].read();
+    assert_eq!(above.ascii_character, b'k');
+    assert_eq!(WRITER.lock().column_position, 0);
+
+    print!("> ");
+    let prompt = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][0].read();
+    assert_eq!(prompt.ascii_character, b'>');
+}
+
+#[test_case]
+fn test_display_width() {
+    assert_eq!(display_width("plain ascii", BUFFER_WIDTH), 11);
+    assert_eq!(display_width("á", BUFFER_WIDTH), 1);
+    assert_eq!(display_width("€", BUFFER_WIDTH), 1);
+    assert_eq!(display_width("ab\tc", BUFFER_WIDTH), 9);
+    assert_eq!(display_width("ab\ncd", BUFFER_WIDTH), 4);
+    assert_eq!(display_width("", BUFFER_WIDTH), 0);
+    assert_eq!(display_width("abcdefgh\tx", 10), 11);
+    assert_eq!(display_width("abcdefghij\tx", 10), 19);
+    assert_eq!(display_width("a\tb\tc", 12), 13);
+}
+
+#[test_case]
+fn test_display_width_matches_output() {
+    prepare_test();
+
+    let s = "a\tb\tá";
+    print!("{}", s);
+
+    assert_eq!(
+        WRITER.lock().column_position,
+        display_width(s, BUFFER_WIDTH)
+    );
+    let after_tab = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][TAB_WIDTH].read();
+    assert_eq!(after_tab.ascii_character, b'b');
+}
+
+#[test_case]
+fn test_frozen_screen_ignores_writes() {
+    prepare_test();
+    print!("before freeze");
+    let frozen = WRITER.lock().snapshot();
+
+    WRITER.lock().set_frozen(true);
+    println!("while frozen");
+    for _ in 0..BUFFER_HEIGHT {
+        println!();
+    }
+    WRITER.lock().clear_current_line();
+    WRITER.lock().clear_screen();
+    assert_eq!(WRITER.lock().snapshot(), frozen);
+
+    WRITER.lock().set_frozen(false);
+    print!("!");
+    let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][13].read();
+    assert_eq!(char_screen.ascii_character, b'!');
+}
+
+#[test_case]
+fn test_column_wraps_at_width_boundary() {
+    prepare_test();
+
+    let mut writer = WRITER.lock();
+    for _ in 0..BUFFER_WIDTH {
+        writer.write_byte(b'x');
+    }
+    assert_eq!(writer.column_position, BUFFER_WIDTH);
+
+    writer.write_byte(b'y');
+    assert_eq!(writer.column_position, 1);
+    assert_eq!(
+        writer.buffer.chars[BUFFER_HEIGHT - 1][0]
+            .read()
+            .ascii_character,
+        b'y'
+    );
+    assert_eq!(
+        writer.buffer.chars[BUFFER_HEIGHT - 2][BUFFER_WIDTH - 1]
+            .read()
+            .ascii_character,
+        b'x'
+    );
+}
+
+#[test_case]
+fn test_describe_output() {
+    prepare_test();
+    WRITER.lock().home();
+    print!("abc");
+    {
+        let mut writer = WRITER.lock();
+        writer.set_wrap_column(40);
+        writer.set_overflow(Overflow::Wrap);
+        writer.set_scroll_margin(2).unwrap();
+        writer.set_frozen(true);
+    }
+    set_serial_mirror(true);
+
+    let mut output = FixedBuf::<2048>::new();
+    WRITER.lock().describe_to(&mut output).unwrap();
+    set_serial_mirror(false);
+    {
+        let mut writer = WRITER.lock();
+        writer.set_frozen(false);
+        writer.set_scroll_margin(0).unwrap();
+        writer.set_overflow(Overflow::Scroll);
+        writer.set_wrap_column(BUFFER_WIDTH);
+    }
+    let text = output.as_str();
+
+    assert!(text.contains("color:         Cyan on Black"), "{}", text);
+    assert!(text.contains("cursor:        row 0, column 3"), "{}", text);
+    assert!(text.contains("tab width:     8"), "{}", text);
+    assert!(text.contains("wrap column:   40"), "{}", text);
+    assert!(text.contains("overflow:      Wrap"), "{}", text);
+    assert!(text.contains("scroll region: rows 0 to 22"), "{}", text);
+    assert!(text.contains("scroll margin: 2"), "{}", text);
+    assert!(text.contains("frozen:        true"), "{}", text);
+    assert!(text.contains("vga output:    enabled"), "{}", text);
+    assert!(text.contains("serial mirror: enabled"), "{}", text);
+}
+
+#[test_case]
+fn test_recent_lines() {
+    prepare_test();
+    for i in 0..RECENT_LINES + 4 {
+        println!("recent {}", i);
+    }
+    print!("unfinished");
+
+    let recent = recent_lines(3);
+    let mut lines = recent.iter();
+    assert_eq!(recent.len(), 3);
+    assert_eq!(lines.next(), Some(&b"recent 17"[..]));
+    assert_eq!(lines.next(), Some(&b"recent 18"[..]));
+    assert_eq!(lines.next(), Some(&b"recent 19"[..]));
+    assert_eq!(lines.next(), None);
+
+    let all = recent_lines(RECENT_LINES + 10);
+    assert_eq!(all.len(), RECENT_LINES);
+    assert_eq!(all.iter().next(), Some(&b"recent 4"[..]));
+
+    let writer = WRITER.lock();
+    let while_locked = try_recent_lines(1);
+    drop(writer);
+    let newest = while_locked.expect("recent lines need the screen lock");
+    assert_eq!(newest.iter().next(), Some(&b"recent 19"[..]));
+}
+
+#[test_case]
+fn test_diff_reports_changed_cells() {
+    prepare_test();
+    let front = WRITER.lock().snapshot();
+    let mut back = front.clone();
+    assert_eq!(diff(&front, &back).count(), 0);
+
+    let changed = [(0, 0), (12, 40), (BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1)];
+    for &(row, col) in changed.iter() {
+        back.0[row][col] = Cell::new(b'#', Color::Yellow, Color::Blue);
+    }
+
+    let mut reported = diff(&front, &back);
+    for &expected in changed.iter() {
+        assert_eq!(reported.next(), Some(expected));
+    }
+    assert_eq!(reported.next(), None);
+
+    WRITER.lock().present(&back);
+    assert_eq!(WRITER.lock().snapshot(), back);
+}
+
+#[test_case]
+fn test_alternate_screen() {
+    prepare_test();
+    println!("shell output");
+    print!("$ less");
+    let original = WRITER.lock().snapshot();
+
+    WRITER.lock().enter_alternate();
+    print!("full screen app");
+    WRITER.lock().enter_alternate();
+    assert_eq!(
+        WRITER.lock().buffer.chars[0][0].read().ascii_character,
+        b'f'
+    );
+    WRITER.lock().leave_alternate();
+
+    assert_eq!(WRITER.lock().snapshot(), original);
+    print!("!");
+    let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][6].read();
+    assert_eq!(char_screen.ascii_character, b'!');
+
+    WRITER.lock().leave_alternate();
+}
+
+#[test_case]
+fn test_last_write_wrapped() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+
+    writer.write_string("short");
+    assert!(!writer.last_write_wrapped());
+
+    writer.clear_current_line();
+    for _ in 0..BUFFER_WIDTH {
+        writer.write_string("x");
+    }
+    assert!(!writer.last_write_wrapped());
+
+    writer.write_string("y");
+    assert!(writer.last_write_wrapped());
+
+    writer.write_string("z");
+    assert!(!writer.last_write_wrapped());
+}
+
+#[test_case]
+fn test_write_then_tab_to() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+
+    writer.write_then_tab_to("name", 20);
+    writer.write_string("size");
+    let row = writer.snapshot().0[BUFFER_HEIGHT - 1];
+    assert_eq!(row[4].ascii_character, b' ');
+    assert_eq!(row[19].ascii_character, b' ');
+    for (i, c) in "size".bytes().enumerate() {
+        assert_eq!(row[20 + i].ascii_character, c);
+    }
+
+    writer.clear_current_line();
+    writer.write_then_tab_to("a name longer than twenty", 20);
+    assert_eq!(writer.column_position, 20);
+    writer.write_then_tab_to("", BUFFER_WIDTH + 5);
+    assert_eq!(writer.column_position, BUFFER_WIDTH);
+
+    writer.clear_current_line();
+    writer.set_wrap_column(10);
+    writer.write_then_tab_to("ab", 40);
+    let narrow = writer.column_position;
+    writer.set_wrap_column(BUFFER_WIDTH);
+    assert_eq!(narrow, 10);
+}
+
+#[test_case]
+fn test_linear_index() {
+    assert_eq!(linear_index(0, 0), Some(0));
+    assert_eq!(linear_index(0, BUFFER_WIDTH - 1), Some(BUFFER_WIDTH - 1));
+    assert_eq!(linear_index(1, 0), Some(BUFFER_WIDTH));
+    let last = BUFFER_HEIGHT * BUFFER_WIDTH - 1;
+    assert_eq!(
+        linear_index(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1),
+        Some(last)
+    );
+
+    assert_eq!(linear_index(BUFFER_HEIGHT, 0), None);
+    assert_eq!(linear_index(0, BUFFER_WIDTH), None);
+    assert_eq!(linear_index(usize::MAX, usize::MAX), None);
+}
+
+#[test_case]
+fn test_from_linear() {
+    assert_eq!(from_linear(0), (0, 0));
+    assert_eq!(from_linear(BUFFER_WIDTH), (1, 0));
+    let last = BUFFER_HEIGHT * BUFFER_WIDTH - 1;
+    assert_eq!(from_linear(last), (BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1));
+    for &idx in [0, 79, 80, 1000, last].iter() {
+        let (row, col) = from_linear(idx);
+        assert_eq!(linear_index(row, col), Some(idx));
+    }
+}
+
+#[test_case]
+fn test_debug_overlay() {
+    prepare_test();
+    set_debug_overlay(true).unwrap();
+    for _ in 0..BUFFER_HEIGHT + 2 {
+        println!("scrolled");
+    }
+    let top = WRITER.lock().snapshot().0[0];
+    WRITER.lock().clear_screen();
+    let after_clear = WRITER.lock().read_char(0, 0);
+    set_debug_overlay(false).unwrap();
+
+    let mut text = [0u8; BUFFER_WIDTH];
+    for (byte, cell) in text.iter_mut().zip(top.iter()) {
+        *byte = cell.ascii_character;
+    }
+    let text = core::str::from_utf8(&text).unwrap();
+    for &label in ["chars ", "scrolls ", "contended ", "panics ", "ticks "].iter() {
+        assert!(text.contains(label), "overlay was {:?}", text);
+    }
+    assert_eq!(after_clear, Some(top[0]));
+    assert_eq!(
+        top[0].color_code,
+        ColorCode::new(Color::Black, Color::LightGrey)
+    );
+
+    let hidden = WRITER.lock().read_char(0, 0).unwrap();
+    assert_eq!(hidden.ascii_character, b' ');
+}
+
+#[test_case]
+fn test_debug_overlay_ticks() {
+    use core::fmt::Write;
+
+    prepare_test();
+    set_debug_overlay(true).unwrap();
+    crate::time::tick();
+    let ticks = crate::time::ticks();
+    let top = WRITER.lock().snapshot().0[0];
+    set_debug_overlay(false).unwrap();
+
+    let mut text = FixedBuf::<BUFFER_WIDTH>::new();
+    for cell in top.iter() {
+        let _ = text.write_char(char::from(cell.ascii_character));
+    }
+    let mut expected = FixedBuf::<32>::new();
+    let _ = write!(expected, "  ticks {} ", ticks);
+    assert!(
+        text.as_str().contains(expected.as_str()),
+        "overlay was {:?}",
+        text.as_str()
+    );
+}
+
+#[test_case]
+fn test_capture_into() {
+    prepare_test();
+    let before = WRITER.lock().snapshot();
+
+    let mut buf = FixedBuf::<32>::new();
+    capture_into(&mut buf, || {
+        println!("captured {}", 42);
+    });
+    assert_eq!(buf.as_str(), "captured 42\n");
+    assert_eq!(WRITER.lock().snapshot(), before);
+
+    let mut small = FixedBuf::<4>::new();
+    let mut inner = FixedBuf::<8>::new();
+    capture_into(&mut small, || {
+        print!("ab");
+        capture_into(&mut inner, || print!("inner"));
+        print!("cdef");
+    });
+    assert_eq!(small.as_str(), "abcd");
+    assert_eq!(inner.as_str(), "inner");
+
+    print!("visible");
+    let char_screen = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 1][0].read();
+    assert_eq!(char_screen.ascii_character, b'v');
+}
+
+#[test_case]
+fn test_write_padded_number() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+
+    let cases: [(u64, usize, bool, &[u8]); 5] = [
+        (7, 3, true, b"007"),
+        (7, 3, false, b"  7"),
+        (0, 2, true, b"00"),
+        (12345, 3, true, b"12345"),
+        (u64::MAX, 0, false, b"18446744073709551615"),
+    ];
+    for &(value, width, zero_pad, expected) in cases.iter() {
+        writer.clear_current_line();
+        writer.write_padded_number(value, width, zero_pad);
+        let row = writer.snapshot().0[BUFFER_HEIGHT - 1];
+        for (i, &c) in expected.iter().enumerate() {
+            assert_eq!(row[i].ascii_character, c, "failed on {}", value);
+        }
+        assert_eq!(writer.column_position, expected.len());
+    }
+}
+
+#[test_case]
+fn test_scroll_margin() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.set_scroll_margin(2).unwrap();
+    writer.clear_screen();
+    writer.write_string("bottom");
+    let bottom = writer.buffer.chars[BUFFER_HEIGHT - 3][0].read();
+    assert_eq!(bottom.ascii_character, b'b');
+
+    writer.home();
+    reset_scroll_count();
+    for _ in 0..BUFFER_HEIGHT - 3 {
+        writer.write_string("line\n");
+    }
+    assert_eq!(scroll_count(), 0);
+    writer.write_string("line\n");
+    assert_eq!(scroll_count(), 1);
+    writer.write_string("last");
+
+    assert_eq!(writer.row_position, BUFFER_HEIGHT - 3);
+    for row in BUFFER_HEIGHT - 2..BUFFER_HEIGHT {
+        let char_screen = writer.buffer.chars[row][0].read();
+        assert_eq!(char_screen.ascii_character, b' ', "row {} not blank", row);
+    }
+
+    writer.set_scroll_margin(0).unwrap();
+    writer.write_string("\nafter");
+    let after = writer.buffer.chars[BUFFER_HEIGHT - 2][0].read();
+    assert_eq!(after.ascii_character, b'a');
+}
+
+#[test_case]
+fn test_color_all() {
+    assert_eq!(Color::ALL.len(), 16);
+    for (i, &color) in Color::ALL.iter().enumerate() {
+        assert_eq!(color as usize, i);
+        assert_eq!(ColorCode::new(color, Color::Black).foreground(), color);
+    }
+    assert_eq!(Color::LightBlue.name(), "light blue");
+    assert_eq!(Color::from_u8(0x9), Ok(Color::LightBlue));
+    assert_eq!(Color::from_u8(0x10), Err(KernelError::InvalidColor(0x10)));
+    assert_eq!(Color::Black.name(), "black");
+}
+
+#[test_case]
+fn test_fallback_count() {
+    prepare_test();
+    reset_fallback_count();
+
+    print!("plain ascii\twith tab\n");
+    assert_eq!(fallback_count(), 0);
+
+    print!("áçãó");
+    assert_eq!(fallback_count(), 1);
+    print!("€○");
+    assert_eq!(fallback_count(), 2);
+
+    reset_fallback_count();
+    assert_eq!(fallback_count(), 0);
+}
+
+#[cfg(test)]
+static FLASH_TOGGLES: AtomicU64 = AtomicU64::new(0);
+#[cfg(test)]
+static FLASH_BLINKING: AtomicBool = AtomicBool::new(false);
+
+/// Stands in for the timer while `flash_message` waits, noting each time the
+/// first cell of the bottom row starts or stops blinking.
+#[cfg(test)]
+fn flash_idle() {
+    crate::time::tick();
+    let writer = WRITER.try_lock().expect("flash_message held the writer");
+    let attribute = writer
+        .read_char(BUFFER_HEIGHT - 1, 0)
+        .unwrap()
+        .color_code
+        .attribute();
+    let blinking = attribute & BLINK != 0;
+    if FLASH_BLINKING.swap(blinking, Ordering::SeqCst) != blinking {
+        FLASH_TOGGLES.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test_case]
+fn test_flash_message() {
+    prepare_test();
+    let color_code = WRITER.lock().color_code;
+    let row = BUFFER_HEIGHT - 1;
+    FLASH_TOGGLES.store(0, Ordering::SeqCst);
+    FLASH_BLINKING.store(false, Ordering::SeqCst);
+
+    flash_message_with_idle(row, "alert", 2, flash_idle);
+
+    let writer = WRITER.lock();
+    assert_eq!(FLASH_TOGGLES.load(Ordering::SeqCst), 4);
+    for (col, &byte) in b"alert".iter().enumerate() {
+        let cell = writer.read_char(row, col).unwrap();
+        assert_eq!(cell.ascii_character, byte);
+        assert_eq!(cell.color_code, color_code);
+    }
+    assert_eq!(writer.color_code, color_code);
+    assert_eq!(writer.column_position, 0);
+}
+
+#[test_case]
+fn test_software_cursor() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.home();
+    writer.write_string("xyz");
+    writer.home();
+    let normal = writer.buffer.chars[0][0].read();
+
+    writer.set_software_cursor(true);
+    writer.blink_cursor();
+    let shown = writer.buffer.chars[0][0].read();
+    writer.blink_cursor();
+    let hidden = writer.buffer.chars[0][0].read();
+
+    assert_eq!(shown.ascii_character, b'x');
+    assert_eq!(shown.color_code, ColorCode::new(Color::Black, Color::Cyan));
+    assert_eq!(hidden, normal);
+
+    writer.blink_cursor();
+    writer.write_string("q");
+    assert_eq!(
+        writer.buffer.chars[0][0].read(),
+        Cell::new(b'q', Color::Cyan, Color::Black)
+    );
+    writer.blink_cursor();
+    writer.write_string("\n");
+    let moved_from = writer.buffer.chars[0][1].read();
+    assert_eq!(moved_from, Cell::new(b'y', Color::Cyan, Color::Black));
+
+    writer.set_software_cursor(false);
+    writer.blink_cursor();
+    assert_eq!(
+        writer.buffer.chars[1][0].read().color_code,
+        normal.color_code
+    );
+}
+
+#[test_case]
+fn test_alternate_hides_software_cursor() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.home();
+    writer.write_string("ab");
+    let under_cursor = writer.read_char(0, 2).unwrap();
+    writer.set_software_cursor(true);
+    writer.blink_cursor();
+
+    writer.enter_alternate();
+    writer.leave_alternate();
+    let restored = writer.read_char(0, 2).unwrap();
+    writer.blink_cursor();
+    let shown = writer.read_char(0, 2).unwrap();
+    writer.set_software_cursor(false);
+
+    assert_eq!(restored, under_cursor);
+    assert_eq!(shown, inverted(under_cursor));
+}
+
+#[test_case]
+fn test_assert_fits() {
+    let line = [b'x'; BUFFER_WIDTH + 1];
+    let line = core::str::from_utf8(&line).unwrap();
+    assert!(assert_fits(&line[..BUFFER_WIDTH], BUFFER_WIDTH));
+    assert!(!assert_fits(line, BUFFER_WIDTH));
+    assert!(assert_fits("a\tb", BUFFER_WIDTH));
+    assert!(!assert_fits("two\nlines", BUFFER_WIDTH));
+
+    assert!(assert_fits("0123456789", 10));
+    assert!(!assert_fits("0123456789a", 10));
+    assert!(assert_fits("012345678\t", 10));
+    assert!(!assert_fits("0123456789\t", 10));
+
+    let writer = WRITER.lock();
+    assert!(assert_fits("while locked", writer.wrap_column()));
+}
+
+#[test_case]
+fn test_write_line_exact() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+
+    assert_eq!(writer.write_line_exact("fits"), Ok(()));
+    assert_eq!(writer.column_position, 4);
+    let before = writer.snapshot();
+
+    let long = [b'x'; BUFFER_WIDTH - 3];
+    let long = core::str::from_utf8(&long).unwrap();
+    assert_eq!(
+        writer.write_line_exact(long),
+        Err(KernelError::LineTooLong {
+            width: Some(BUFFER_WIDTH - 3),
+            available: BUFFER_WIDTH - 4,
+        })
+    );
+    assert_eq!(writer.snapshot(), before);
+    assert_eq!(writer.column_position, 4);
+
+    assert_eq!(
+        writer.write_line_exact("a\nb"),
+        Err(KernelError::LineTooLong {
+            width: None,
+            available: BUFFER_WIDTH - 4,
+        })
+    );
+    assert_eq!(writer.snapshot(), before);
+
+    writer.set_wrap_column(10);
+    let narrow = writer.write_line_exact("1234567");
+    writer.set_wrap_column(BUFFER_WIDTH);
+    assert_eq!(
+        narrow,
+        Err(KernelError::LineTooLong {
+            width: Some(7),
+            available: 6,
+        })
+    );
+    assert_eq!(writer.snapshot(), before);
+}
+
+#[test_case]
+fn test_blit_transparent() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    let background = Cell::new(b'.', Color::DarkGray, Color::Blue);
+    for row in 0..BUFFER_HEIGHT {
+        writer.write_row(row, &[background; BUFFER_WIDTH]);
+    }
+
+    let x = Cell::new(b'x', Color::Yellow, Color::Black);
+    let blank = Cell::new(b' ', Color::Yellow, Color::Black);
+    let sprite = [x, blank, x, blank, x, blank];
+    writer.blit_transparent(2, 10, &sprite, 3);
+    writer.blit_transparent(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1, &sprite, 3);
+
+    let screen = writer.snapshot();
+    assert_eq!(screen.0[2][10], x);
+    assert_eq!(screen.0[2][11], background);
+    assert_eq!(screen.0[2][12], x);
+    assert_eq!(screen.0[3][10], background);
+    assert_eq!(screen.0[3][11], x);
+    assert_eq!(screen.0[3][12], background);
+    assert_eq!(screen.0[BUFFER_HEIGHT - 1][BUFFER_WIDTH - 1], x);
+
+    let changed = screen
+        .0
+        .iter()
+        .flat_map(|row| row.iter())
+        .filter(|&&cell| cell != background)
+        .count();
+    assert_eq!(changed, 4);
+}
+
+#[test_case]
+fn test_hr() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.write_string("menu");
+    writer.hr();
+
+    let screen = writer.snapshot();
+    assert_eq!(screen.0[BUFFER_HEIGHT - 3][0].ascii_character, b'm');
+    for cell in screen.0[BUFFER_HEIGHT - 2].iter() {
+        assert_eq!(cell.ascii_character, 0xC4);
+        assert_eq!(cell.color_code, writer.color_code);
+    }
+    assert_eq!(writer.row_position, BUFFER_HEIGHT - 1);
+    assert_eq!(writer.column_position, 0);
+
+    writer.hr_char(b'=');
+    let line = writer.snapshot().0[BUFFER_HEIGHT - 2];
+    assert!(line.iter().all(|cell| cell.ascii_character == b'='));
+    assert_eq!(writer.column_position, 0);
+}
+
+#[test_case]
+fn test_clear_color() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    let text_color = writer.color_code;
+    writer.clear_screen();
+    assert_eq!(writer.read_char(0, 0).unwrap().color_code, text_color);
+
+    writer.set_clear_color(Color::White, Color::Blue);
+    writer.set_color(Color::Yellow, Color::Red);
+    writer.write_string("warning\n");
+    let text = writer.read_char(BUFFER_HEIGHT - 2, 0).unwrap();
+    let scrolled_in = writer.read_char(BUFFER_HEIGHT - 1, 0).unwrap();
+    writer.clear_screen();
+    let cleared = writer.read_char(3, 40).unwrap();
+
+    writer.set_color(Color::Cyan, Color::Black);
+    writer.clear_color = None;
+
+    let clear_color = ColorCode::new(Color::White, Color::Blue);
+    assert_eq!(text.color_code, ColorCode::new(Color::Yellow, Color::Red));
+    assert_eq!(scrolled_in, Cell::new(b' ', Color::White, Color::Blue));
+    assert_eq!(cleared.color_code, clear_color);
+}
+
+#[test_case]
+fn test_current_theme() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.apply_theme(Theme::PAPER);
+    let applied = writer.current_theme();
+    let color_code = writer.color_code;
+    writer.set_foreground(Color::Red);
+    let after_foreground = writer.current_theme();
+    let red_on_grey = writer.color_code;
+    writer.apply_theme(Theme::PAPER);
+    writer.set_background(Color::Blue);
+    let after_background = writer.current_theme();
+    writer.apply_theme(Theme::DEFAULT);
+
+    assert_eq!(applied, Some(Theme::PAPER));
+    assert_eq!(color_code, ColorCode::new(Color::Black, Color::LightGrey));
+    assert_eq!(after_foreground, None);
+    assert_eq!(red_on_grey, ColorCode::new(Color::Red, Color::LightGrey));
+    assert_eq!(after_background, None);
+    assert_eq!(writer.current_theme(), Some(Theme::DEFAULT));
+}
+
+#[test_case]
+fn test_wrap_column() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    assert_eq!(writer.wrap_column(), BUFFER_WIDTH);
+
+    writer.set_wrap_column(40);
+    let s = [b'w'; 50];
+    writer.write_string(core::str::from_utf8(&s).unwrap());
+    let screen = writer.snapshot();
+    let column = writer.column_position;
+
+    writer.set_wrap_column(0);
+    let narrowest = writer.wrap_column();
+    writer.set_wrap_column(BUFFER_WIDTH + 1);
+
+    assert!(writer.last_write_wrapped());
+    assert_eq!(screen.0[BUFFER_HEIGHT - 2][39].ascii_character, b'w');
+    assert_eq!(screen.0[BUFFER_HEIGHT - 2][40].ascii_character, b' ');
+    assert_eq!(screen.0[BUFFER_HEIGHT - 1][9].ascii_character, b'w');
+    assert_eq!(column, 10);
+    assert_eq!(narrowest, 1);
+    assert_eq!(writer.wrap_column(), BUFFER_WIDTH);
+}
+
+#[test_case]
+fn test_wrap_column_tabs() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.set_wrap_column(10);
+
+    let short = "abcdefgh\tx";
+    writer.write_string(short);
+    let short_end = writer.column_position;
+    writer.clear_screen();
+    let full = "abcdefghij\tx";
+    writer.write_string(full);
+    let full_end = writer.column_position;
+    let screen = writer.snapshot();
+    writer.set_wrap_column(BUFFER_WIDTH);
+
+    // A tab at column 8 stops at the wrap column, and one at the wrap point
+    // goes to the first tab stop of the next row.
+    assert_eq!(short_end, 1);
+    assert_eq!(display_width(short, 10), 10 + short_end);
+    assert_eq!(screen.0[BUFFER_HEIGHT - 1][TAB_WIDTH].ascii_character, b'x');
+    assert_eq!(full_end, TAB_WIDTH + 1);
+    assert_eq!(display_width(full, 10), 10 + full_end);
+}
+
+#[test_case]
+fn test_sparkline() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.sparkline(5, &[0, 4, 8, 200]);
+    writer.sparkline(BUFFER_HEIGHT, &[8]);
+
+    let glyph = |col: usize| writer.buffer.chars[5][col].read().ascii_character;
+    assert_eq!(glyph(0), b' ');
+    assert_eq!(glyph(1), 0xB1);
+    assert_eq!(glyph(2), 0xDB);
+    assert_eq!(glyph(3), 0xDB);
+    assert_eq!(glyph(4), b' ');
+}
+
+#[cfg(test)]
+fn fill_screen(writer: &mut Writer) {
+    use core::fmt::Write;
+
+    writer.clear_screen();
+    writer.home();
+    for row in 0..BUFFER_HEIGHT - 1 {
+        writeln!(writer, "row {}", row).unwrap();
+    }
+    writer.write_string("last");
+}
+
+#[test_case]
+fn test_overflow_scroll() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    fill_screen(&mut writer);
+    writer.write_string("\nmore");
+
+    let screen = writer.snapshot();
+    assert_eq!(screen.0[0][4].ascii_character, b'1');
+    assert_eq!(screen.0[BUFFER_HEIGHT - 2][0].ascii_character, b'l');
+    assert_eq!(screen.0[BUFFER_HEIGHT - 1][0].ascii_character, b'm');
+}
+
+#[test_case]
+fn test_overflow_discard() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.set_overflow(Overflow::Discard);
+    fill_screen(&mut writer);
+    let full = writer.snapshot();
+
+    writer.write_string("\nmore");
+    writer.write_string("and more");
+    writer.write_then_tab_to("field", 40);
+    let after = writer.snapshot();
+    writer.clear_screen();
+    writer.write_string("cleared");
+    let resumed = writer.buffer.chars[BUFFER_HEIGHT - 1][0].read();
+    writer.set_overflow(Overflow::Scroll);
+
+    assert_eq!(after, full);
+    assert_eq!(resumed.ascii_character, b'c');
+}
+
+#[test_case]
+fn test_overflow_discard_after_clear() {
+    use core::fmt::Write;
+
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.set_overflow(Overflow::Discard);
+    writer.clear_screen();
+    for row in 0..BUFFER_HEIGHT {
+        writeln!(writer, "row {}", row).unwrap();
+    }
+    writer.write_string("dropped");
+    let recent = recent_lines(1);
+    writer.hr_char(b'-');
+    writer.write_kv("mode", "text");
+    writer.write_banner("banner", Color::White, Color::Red);
+    let recent_after = recent_lines(1);
+    let screen = writer.snapshot();
+    writer.set_overflow(Overflow::Scroll);
+    writer.clear_screen();
+
+    assert_eq!(screen.0[0][4].ascii_character, b'0');
+    assert_eq!(screen.0[BUFFER_HEIGHT - 1][4].ascii_character, b'2');
+    assert_eq!(screen.0[BUFFER_HEIGHT - 1][5].ascii_character, b'4');
+    assert_eq!(recent.iter().next(), Some(&b"row 24"[..]));
+    assert_eq!(recent_after.len(), 1);
+    assert_eq!(recent_after.iter().next(), recent.iter().next());
+}
+
+#[test_case]
+fn test_overflow_wrap() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.set_overflow(Overflow::Wrap);
+    fill_screen(&mut writer);
+    writer.write_string("\nmore");
+    let screen = writer.snapshot();
+    let row = writer.row_position;
+    writer.set_overflow(Overflow::Scroll);
+
+    assert_eq!(row, 0);
+    assert_eq!(screen.0[0][0].ascii_character, b'm');
+    assert_eq!(screen.0[0][4].ascii_character, b' ');
+    assert_eq!(screen.0[1][4].ascii_character, b'1');
+    assert_eq!(screen.0[BUFFER_HEIGHT - 1][0].ascii_character, b'l');
+}
+
+#[test_case]
+fn test_write_kv() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.write_string("partial");
+    writer.write_kv("mode", "text");
+    writer.write_kv("scroll margin", "2");
+
+    let screen = writer.snapshot();
+    let first = &screen.0[BUFFER_HEIGHT - 3];
+    let second = &screen.0[BUFFER_HEIGHT - 2];
+    let dim = ColorCode::new(Color::DarkGray, Color::Black);
+
+    assert_eq!(screen.0[BUFFER_HEIGHT - 4][0].ascii_character, b'p');
+    assert_eq!(first[0], Cell::new(b'm', Color::DarkGray, Color::Black));
+    assert_eq!(first[3].color_code, dim);
+    assert_eq!(first[4], Cell::new(b':', Color::Cyan, Color::Black));
+    assert_eq!(second[12].color_code, dim);
+    assert_eq!(first[KV_VALUE_COLUMN - 1].ascii_character, b' ');
+    assert_eq!(
+        first[KV_VALUE_COLUMN],
+        Cell::new(b't', Color::Cyan, Color::Black)
+    );
+    assert_eq!(second[KV_VALUE_COLUMN - 1].ascii_character, b' ');
+    assert_eq!(
+        second[KV_VALUE_COLUMN],
+        Cell::new(b'2', Color::Cyan, Color::Black)
+    );
+    assert_eq!(writer.column_position, 0);
+}
+
+#[test_case]
+fn test_write_kv_narrow_or_discarding() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.set_wrap_column(10);
+    writer.write_kv("mode", "text");
+    let narrow = writer.snapshot();
+    writer.set_wrap_column(BUFFER_WIDTH);
+
+    writer.set_overflow(Overflow::Discard);
+    fill_screen(&mut writer);
+    writer.write_string("\n");
+    let full = writer.snapshot();
+    writer.write_kv("mode", "text");
+    let after = writer.snapshot();
+    writer.set_overflow(Overflow::Scroll);
+    writer.clear_screen();
+
+    let row = &narrow.0[BUFFER_HEIGHT - 3];
+    assert_eq!(row[0].ascii_character, b'm');
+    assert_eq!(row[9].ascii_character, b' ');
+    assert_eq!(narrow.0[BUFFER_HEIGHT - 2][0].ascii_character, b't');
+    assert_eq!(after, full);
+}
+
+#[test_case]
+fn test_load_template() {
+    const TEMPLATE: &str = concat!(
+        "Menu\n",
+        "0123456789012345678901234567890123456789",
+        "0123456789012345678901234567890123456789",
+        "cut off",
+    );
+
+    prepare_test();
+    let mut writer = WRITER.lock();
+    let marker = Cell::new(b'#', Color::Red, Color::Black);
+    writer.write_row(2, &[marker; BUFFER_WIDTH]);
+    writer.load_template(TEMPLATE);
+
+    let screen = writer.snapshot();
+    let blank = Cell::new(b' ', Color::Cyan, Color::Black);
+    for (col, &c) in b"Menu".iter().enumerate() {
+        assert_eq!(screen.0[0][col], Cell::new(c, Color::Cyan, Color::Black));
+    }
+    assert!(screen.0[0][4..].iter().all(|&cell| cell == blank));
+    for (col, cell) in screen.0[1].iter().enumerate() {
+        assert_eq!(cell.ascii_character, b'0' + (col % 10) as u8);
+    }
+    assert_eq!(screen.0[2][0], marker);
+    assert_eq!(writer.row_position, BUFFER_HEIGHT - 1);
+
+    writer.clear_screen();
+    writer
+        .set_layout(Layout {
+            scroll_top: 3,
+            scroll_bottom: BUFFER_HEIGHT - 1,
+        })
+        .unwrap();
+    writer.load_template("Header");
+    let header = writer.read_char(0, 0).unwrap();
+    writer
+        .set_layout(Layout {
+            scroll_top: 0,
+            scroll_bottom: BUFFER_HEIGHT - 1,
+        })
+        .unwrap();
+    assert_eq!(header.ascii_character, b'H');
+}
+
+#[test_case]
+fn test_write_banner() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    writer.write_string("before");
+    writer.write_banner("alert", Color::White, Color::Red);
+
+    let banner = ColorCode::new(Color::White, Color::Red);
+    let row = BUFFER_HEIGHT - 2;
+    for (col, &byte) in b"alert".iter().enumerate() {
+        let cell = writer.read_char(row, col).unwrap();
+        assert_eq!(cell.ascii_character, byte);
+        assert_eq!(cell.color_code, banner);
+    }
+    let last = writer.read_char(row, BUFFER_WIDTH - 1).unwrap();
+    assert_eq!(last.ascii_character, b' ');
+    assert_eq!(last.color_code, banner);
+    assert_eq!(writer.read_char(row - 1, 0).unwrap().ascii_character, b'b');
+    assert_eq!(writer.column_position, 0);
+    assert_ne!(writer.color_code, banner);
+}
+
+#[test_case]
+fn test_dirty_range() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    let row = BUFFER_HEIGHT - 1;
+    assert_eq!(writer.dirty_range(row), None);
+
+    writer.column_position = 10;
+    writer.write_string("abc");
+    writer.column_position = 4;
+    writer.write_string("x");
+    assert_eq!(writer.dirty_range(row), Some((4, 12)));
+    assert_eq!(writer.dirty_range(row - 1), None);
+    assert_eq!(writer.dirty_range(BUFFER_HEIGHT), None);
+
+    let back = writer.snapshot();
+    writer.present(&back);
+    assert_eq!(writer.dirty_range(row), None);
+
+    writer.write_string("y");
+    writer.clear_screen();
+    assert_eq!(writer.dirty_range(row), None);
+}
+
+#[test_case]
+fn test_occupied_cells() {
+    prepare_test();
+    let mut writer = WRITER.lock();
+    assert_eq!(writer.occupied_cells(), 0);
+
+    writer.write_string("one two\nthree");
+    assert_eq!(writer.occupied_cells(), 11);
+    assert_eq!(writer.row_occupied(BUFFER_HEIGHT - 2), 6);
+    assert_eq!(writer.row_occupied(BUFFER_HEIGHT - 1), 5);
+    assert_eq!(writer.row_occupied(BUFFER_HEIGHT), 0);
+
+    writer.clear_screen();
+    assert_eq!(writer.occupied_cells(), 0);
+}
+
+#[test_case]
+fn test_set_layout() {
+    prepare_test();
+    let full = Layout {
+        scroll_top: 0,
+        scroll_bottom: BUFFER_HEIGHT - 1,
+    };
+    let region = Layout {
+        scroll_top: 2,
+        scroll_bottom: 20,
+    };
+    let mut writer = WRITER.lock();
+    assert_eq!(writer.layout(), full);
+
+    assert_eq!(writer.set_layout(region), Ok(()));
+    assert_eq!(writer.layout(), region);
+    assert_eq!(writer.encode_state()[3] & STATE_DEBUG_OVERLAY, 0);
+    assert_eq!(writer.row_position, 20);
+    writer.home();
+    assert_eq!(writer.row_position, 2);
+
+    let inverted = Layout {
+        scroll_top: 10,
+        scroll_bottom: 9,
+    };
+    let off_screen = Layout {
+        scroll_top: 0,
+        scroll_bottom: BUFFER_HEIGHT,
+    };
+    assert_eq!(writer.set_layout(inverted), Err(KernelError::InvalidLayout));
+    assert_eq!(
+        writer.set_layout(off_screen),
+        Err(KernelError::InvalidLayout)
+    );
+    assert_eq!(
+        writer.set_scroll_margin(BUFFER_HEIGHT),
+        Err(KernelError::InvalidLayout)
+    );
+    assert_eq!(writer.layout(), region);
+
+    writer.set_layout(full).unwrap();
+}
+
+#[test_case]
+fn test_layout_overlapping_debug_overlay() {
+    prepare_test();
+    set_debug_overlay(true).unwrap();
+    let overlay_flag = WRITER.lock().encode_state()[3] & STATE_DEBUG_OVERLAY;
+    let header_overlap = WRITER.lock().set_layout(Layout {
+        scroll_top: 0,
+        scroll_bottom: 10,
+    });
+    let layout = WRITER.lock().layout();
+    set_debug_overlay(false).unwrap();
+
+    let last_row_only = Layout {
+        scroll_top: 0,
+        scroll_bottom: 0,
+    };
+    WRITER.lock().set_layout(last_row_only).unwrap();
+    let overlay = set_debug_overlay(true);
+    let unchanged = WRITER.lock().layout();
+    WRITER
+        .lock()
+        .set_layout(Layout {
+            scroll_top: 0,
+            scroll_bottom: BUFFER_HEIGHT - 1,
+        })
+        .unwrap();
+
+    assert_eq!(overlay_flag, STATE_DEBUG_OVERLAY);
+    assert_eq!(header_overlap, Err(KernelError::InvalidLayout));
+    assert_eq!(
+        layout,
+        Layout {
+            scroll_top: 1,
+            scroll_bottom: BUFFER_HEIGHT - 1,
+        }
+    );
+    assert_eq!(overlay, Err(KernelError::InvalidLayout));
+    assert_eq!(unchanged, last_row_only);
+    assert!(!DEBUG_OVERLAY.load(Ordering::SeqCst));
 }
 
 #[cfg(test)]
@@ -295,7 +3235,6 @@ fn print_screen_serial() {
 }
 
 #[cfg(test)]
-fn prepare_test(name: &str) {
+fn prepare_test() {
     WRITER.lock().clear_screen();
-    serial_print!("{}", name);
 }
@@ -4,7 +4,7 @@
 #![test_runner(alice_os::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
-use alice_os::{println, serial_print, serial_println};
+use alice_os::println;
 use core::panic::PanicInfo;
 
 #[no_mangle] // don't mangle the name of this function
@@ -20,7 +20,5 @@ fn panic(info: &PanicInfo) -> ! {
 
 #[test_case]
 fn test_println() {
-    serial_print!("test_println... ");
     println!("test_println output");
-    serial_println!("[ok]");
 }
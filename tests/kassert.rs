@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use alice_os::fixed_buf::FixedBuf;
+use alice_os::vga_buffer::{Color, ColorCode, BUFFER_HEIGHT, WRITER};
+use alice_os::{exit_qemu, kassert, serial_print, serial_println, QemuExitCode};
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+static KASSERT_LINE: AtomicU32 = AtomicU32::new(0);
+
+#[test_case]
+fn failing_kassert_shows_banner() {
+    serial_print!("failing_kassert_shows_banner... ");
+    KASSERT_LINE.store(line!() + 1, Ordering::SeqCst);
+    kassert!(1 + 1 == 3);
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    test_main();
+    loop {}
+}
+
+pub fn test_runner(tests: &[&dyn Fn()]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test();
+        serial_println!("[test did not panic]");
+        exit_qemu(QemuExitCode::Failed);
+    }
+    exit_qemu(QemuExitCode::Success);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    let mut expected = FixedBuf::<80>::new();
+    alice_os::panic::write_assert_message(
+        &mut |piece| {
+            let _ = expected.write_str(piece);
+        },
+        "1 + 1 == 3",
+        file!(),
+        KASSERT_LINE.load(Ordering::SeqCst),
+    );
+
+    let banner = ColorCode::new(Color::White, Color::Red);
+    let writer = WRITER.lock();
+    let shown = expected.as_str().bytes().enumerate().all(|(col, byte)| {
+        let cell = writer.read_char(BUFFER_HEIGHT - 2, col).unwrap();
+        cell.ascii_character == byte && cell.color_code == banner
+    });
+
+    if shown {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[banner not shown]");
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use alice_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static HOOK_CALLED: AtomicBool = AtomicBool::new(false);
+
+fn hook(_info: &PanicInfo) {
+    HOOK_CALLED.store(true, Ordering::SeqCst);
+}
+
+#[test_case]
+fn panic_runs_hook() {
+    serial_print!("panic_runs_hook... ");
+    alice_os::panic::set_hook(hook);
+    panic!("calling the hook");
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    test_main();
+    loop {}
+}
+
+pub fn test_runner(tests: &[&dyn Fn()]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test();
+        serial_println!("[test did not panic]");
+        exit_qemu(QemuExitCode::Failed);
+    }
+    exit_qemu(QemuExitCode::Success);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    alice_os::panic::run_hook(info);
+    if HOOK_CALLED.load(Ordering::SeqCst) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[hook was not called]");
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use alice_os::fixed_buf::FixedBuf;
+use alice_os::{exit_qemu, println, serial_print, serial_println, QemuExitCode};
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+#[test_case]
+fn panic_reports_recent_output() {
+    serial_print!("panic_reports_recent_output... ");
+    for i in 0..8 {
+        println!("line {}", i);
+    }
+    // Recent output must not depend on the screen lock.
+    let _writer = alice_os::vga_buffer::WRITER.lock();
+    panic!("after printing");
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    test_main();
+    loop {}
+}
+
+pub fn test_runner(tests: &[&dyn Fn()]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test();
+        serial_println!("[test did not panic]");
+        exit_qemu(QemuExitCode::Failed);
+    }
+    exit_qemu(QemuExitCode::Success);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut report = FixedBuf::<512>::new();
+    alice_os::panic::print_to(info, &mut |piece| {
+        let _ = report.write_str(piece);
+    });
+
+    let report = report.as_str();
+    let recent = "Recent output:\n  line 3\n  line 4\n  line 5\n  line 6\n  line 7\n";
+    if report.starts_with("panicked at tests/panic_recent_output.rs:")
+        && report.ends_with(recent)
+        && report.contains(": after printing\n")
+    {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[unexpected panic report]");
+        serial_println!("{}", report);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}